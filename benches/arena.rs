@@ -0,0 +1,75 @@
+//! Compares grouping parenthesized expressions by cloning and splicing
+//! vectors of parse tokens (as the lispy example's `eval` does) against
+//! grouping them in a [ParseArena]. Run with `cargo bench`.
+
+use blarse::arena::*;
+use blarse::parse_token::*;
+use std::time::Instant;
+
+fn char_leaves(body: &str) -> Vec<ParseToken<'_>> {
+    body.char_indices()
+        .filter(|(_, c)| !c.is_whitespace())
+        .map(|(i, c)| ParseToken::new_leaf(Token {
+            body,
+            indices: i..i + c.len_utf8(),
+            tags: vec![&body[i..i + c.len_utf8()]]
+        }))
+        .collect()
+}
+
+fn eval(mut pts: Vec<ParseToken>) -> Vec<ParseToken> {
+    let mut l_index: Option<usize> = None;
+
+    for i in 0..pts.len() {
+        if pts[i].has_tag("(") {
+            l_index = Some(i);
+        } else if let Some(l) = l_index {
+            if pts[i].has_tag(")") {
+                let pts_slice = pts[(l + 1)..i].to_vec();
+                let new_expr = ParseToken::new_branch_from_first(
+                    eval(pts_slice),
+                    vec!["expr"]);
+                pts.splice(l..=i, vec![new_expr]);
+                return eval(pts);
+            }
+        }
+    }
+    pts
+}
+
+fn eval_arena(pts: Vec<ParseToken>) -> Vec<ParseToken> {
+    let mut arena = ParseArena::new();
+    let mut open: Vec<Vec<NodeId>> = vec![vec![]];
+    for pt in pts {
+        if pt.has_tag("(") {
+            open.push(vec![]);
+        } else if pt.has_tag(")") && open.len() > 1 {
+            // There was more than one open group, so unwrap() is safe
+            let children = open.pop().unwrap();
+            let expr = arena.new_branch(children, pt.body, vec!["expr"]);
+            open.last_mut().unwrap().push(expr);
+        } else {
+            let leaf = arena.add_tree(&pt);
+            open.last_mut().unwrap().push(leaf);
+        }
+    }
+    open.concat().into_iter().map(|id| arena.to_tree(id)).collect()
+}
+
+fn time<F: Fn() -> usize>(name: &str, runs: u32, f: F) {
+    let start = Instant::now();
+    let mut total = 0;
+    for _ in 0..runs {
+        total += f();
+    }
+    println!("{:<8} {:>10.2?} per run ({} tokens)", name, start.elapsed() / runs, total / runs as usize);
+}
+
+fn main() {
+    let body = "(define (rgb-series mk) (vc-append (series (lambda (sz) (colorize (mk sz) red)))))".repeat(40);
+    let pts = char_leaves(&body);
+
+    assert!(eval(pts.clone()) == eval_arena(pts.clone()));
+    time("clone", 20, || eval(pts.clone()).len());
+    time("arena", 20, || eval_arena(pts.clone()).len());
+}
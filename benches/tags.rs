@@ -0,0 +1,100 @@
+//! Counts the heap allocations made while building and grouping the parse
+//! tokens of the lispy example, and how many of them are small tag and
+//! children vectors that inline storage would avoid. Run with
+//! `cargo bench --bench tags`.
+
+use blarse::parse_token::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const RGB_SERIES: &str = "
+(define (rgb-series mk)
+  (vc-append
+   (series (lambda (sz) (colorize (mk sz) \"red\")))
+   (series (lambda (sz) (colorize (mk sz) \"green\")))
+   (series (lambda (sz) (colorize (mk sz) \"blue\")))))";
+
+fn char_leaves(body: &str) -> Vec<ParseToken<'_>> {
+    body.char_indices()
+        .filter(|(_, c)| !c.is_whitespace())
+        .map(|(i, c)| ParseToken::new_leaf(Token {
+            body,
+            indices: i..i + c.len_utf8(),
+            tags: vec![&body[i..i + c.len_utf8()]]
+        }))
+        .collect()
+}
+
+fn eval(mut pts: Vec<ParseToken>) -> Vec<ParseToken> {
+    let mut l_index: Option<usize> = None;
+
+    for i in 0..pts.len() {
+        if pts[i].has_tag("(") {
+            l_index = Some(i);
+        } else if let Some(l) = l_index {
+            if pts[i].has_tag(")") {
+                let pts_slice = pts[(l + 1)..i].to_vec();
+                let new_expr = ParseToken::new_branch_from_first(
+                    eval(pts_slice),
+                    vec!["expr"]);
+                pts.splice(l..=i, vec![new_expr]);
+                return eval(pts);
+            }
+        }
+    }
+    pts
+}
+
+/// Counts the tag vectors with at most two entries and the children vectors
+/// with at most four entries in a tree, i.e. the ones that would fit inline.
+fn small_vecs(pt: &ParseToken) -> (usize, usize) {
+    let tags = usize::from(!pt.tags.is_empty() && pt.tags.len() <= 2);
+    match pt.children() {
+        Some(children) => children.iter()
+            .map(small_vecs)
+            .fold((tags, usize::from(!children.is_empty() && children.len() <= 4)),
+                |(t, c), (ct, cc)| (t + ct, c + cc)),
+        None => (tags, 0),
+    }
+}
+
+fn count<F: Fn() -> Vec<ParseToken<'static>>>(name: &str, runs: u32, f: F) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut last = vec![];
+    for _ in 0..runs {
+        last = f();
+    }
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - before) / runs as usize;
+    let (tags, children) = last.iter()
+        .map(small_vecs)
+        .fold((0, 0), |(t, c), (ct, cc)| (t + ct, c + cc));
+    println!("{:<8} {:>6} allocations per run {:>10.2?} per run ({} small tag vectors, {} small children vectors)",
+        name, allocations, start.elapsed() / runs, tags, children);
+}
+
+fn main() {
+    let pts = char_leaves(RGB_SERIES);
+
+    count("leaves", 1000, || char_leaves(RGB_SERIES));
+    count("group", 1000, || eval(pts.clone()));
+}
@@ -0,0 +1,143 @@
+use super::parse_token::*;
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Identifies a node stored in a [ParseArena].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId(usize);
+
+#[derive(Clone)]
+enum ArenaNode {
+    Leaf(Range<usize>),
+    Branch(Vec<NodeId>)
+}
+
+#[derive(Clone)]
+struct ArenaEntry<'a> {
+    node: ArenaNode,
+    body: &'a str,
+    tags: Vec<Cow<'a, str>>
+}
+
+/// A flat store of parse tokens, where branches refer to their children by
+/// [NodeId] instead of owning them. Grouping a run of nodes into a branch only
+/// moves ids around, so rewriting a large tree doesn't require deep clones.
+/// Use [ParseArena::to_tree] to get an ordinary [ParseToken] back.
+#[derive(Clone, Default)]
+pub struct ParseArena<'a> {
+    nodes: Vec<ArenaEntry<'a>>
+}
+
+impl<'a> ParseArena<'a> {
+    pub fn new() -> ParseArena<'a> {
+        ParseArena::default()
+    }
+
+    /// The number of nodes stored in the arena, including any that are no
+    /// longer reachable from a root.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn push(&mut self, node: ArenaNode, body: &'a str, tags: Vec<Cow<'a, str>>) -> NodeId {
+        self.nodes.push(ArenaEntry { node, body, tags });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// See [ParseToken::new_leaf].
+    pub fn new_leaf(&mut self, tok: Token<'a>) -> NodeId {
+        self.push(ArenaNode::Leaf(tok.indices), tok.body, tok.tags.into_iter().map(Cow::Borrowed).collect())
+    }
+
+    /// See [ParseToken::new_branch].
+    pub fn new_branch(&mut self, children: Vec<NodeId>, body: &'a str, tags: Vec<&'a str>) -> NodeId {
+        self.push(ArenaNode::Branch(children), body, tags.into_iter().map(Cow::Borrowed).collect())
+    }
+
+    /// See [ParseToken::try_new_branch_from_first]. Returns None if `children`
+    /// is empty.
+    pub fn new_branch_from_first(&mut self, children: Vec<NodeId>, tags: Vec<&'a str>) -> Option<NodeId> {
+        let body = self.nodes[children.first()?.0].body;
+        Some(self.new_branch(children, body, tags))
+    }
+
+    /// Copies an existing tree into the arena, returning the id of its root.
+    pub fn add_tree(&mut self, pt: &ParseToken<'a>) -> NodeId {
+        match &pt.node {
+            ParseNode::Leaf(r) => self.push(ArenaNode::Leaf(r.clone()), pt.body, pt.tags.clone()),
+            ParseNode::Branch(children) => {
+                let ids = children.iter().map(|c| self.add_tree(c)).collect();
+                self.push(ArenaNode::Branch(ids), pt.body, pt.tags.clone())
+            }
+        }
+    }
+
+    pub fn tags(&self, id: NodeId) -> &[Cow<'a, str>] {
+        &self.nodes[id.0].tags
+    }
+
+    pub fn tags_mut(&mut self, id: NodeId) -> &mut Vec<Cow<'a, str>> {
+        &mut self.nodes[id.0].tags
+    }
+
+    pub fn has_tag(&self, id: NodeId, tag: &str) -> bool {
+        self.tags(id).iter().any(|t| t == tag)
+    }
+
+    /// The children of a branch, or None for a leaf.
+    pub fn children(&self, id: NodeId) -> Option<&[NodeId]> {
+        match &self.nodes[id.0].node {
+            ArenaNode::Leaf(_) => None,
+            ArenaNode::Branch(children) => Some(children)
+        }
+    }
+
+    /// The children of a branch, for editing in place, or None for a leaf.
+    pub fn children_mut(&mut self, id: NodeId) -> Option<&mut Vec<NodeId>> {
+        match &mut self.nodes[id.0].node {
+            ArenaNode::Leaf(_) => None,
+            ArenaNode::Branch(children) => Some(children)
+        }
+    }
+
+    /// See [ParseToken::content_range].
+    pub fn content_range(&self, id: NodeId) -> Option<Range<usize>> {
+        let mut range: Option<Range<usize>> = None;
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            match &self.nodes[id.0].node {
+                ArenaNode::Leaf(r) => {
+                    range = Some(match range {
+                        Some(a) => a.start.min(r.start)..a.end.max(r.end),
+                        None => r.clone()
+                    });
+                },
+                ArenaNode::Branch(children) => stack.extend(children)
+            }
+        }
+        range
+    }
+
+    /// See [ParseToken::content].
+    pub fn content(&self, id: NodeId) -> &'a str {
+        match self.content_range(id) {
+            Some(cr) => self.nodes[id.0].body.get(cr).unwrap_or_default(),
+            None => ""
+        }
+    }
+
+    /// Builds an ordinary tree from the node with the given id and everything
+    /// beneath it.
+    pub fn to_tree(&self, root: NodeId) -> ParseToken<'a> {
+        let entry = &self.nodes[root.0];
+        let node = match &entry.node {
+            ArenaNode::Leaf(r) => ParseNode::Leaf(r.clone()),
+            ArenaNode::Branch(children) => ParseNode::Branch(children.iter().map(|c| self.to_tree(*c)).collect())
+        };
+        ParseToken { node, body: entry.body, tags: entry.tags.clone() }
+    }
+}
@@ -0,0 +1,74 @@
+use super::parse_token::*;
+
+/// Assembles a tree of parse tokens from the top down. Calling
+/// [ParseTokenBuilder::branch] opens a branch, which collects every leaf and
+/// child added until the matching [ParseTokenBuilder::end].
+#[derive(Clone, Default)]
+pub struct ParseTokenBuilder<'a> {
+    /// The branches that have been opened but not yet ended, innermost last,
+    /// along with the children collected for each so far.
+    open: Vec<(Vec<&'a str>, Vec<ParseToken<'a>>)>,
+    roots: Vec<ParseToken<'a>>,
+    invalid: bool
+}
+
+impl<'a> ParseTokenBuilder<'a> {
+    pub fn new() -> ParseTokenBuilder<'a> {
+        ParseTokenBuilder::default()
+    }
+
+    /// Adds a leaf built from a token to the innermost open branch.
+    pub fn leaf(self, tok: Token<'a>) -> ParseTokenBuilder<'a> {
+        self.child(ParseToken::new_leaf(tok))
+    }
+
+    /// Adds an already-built parse token to the innermost open branch.
+    pub fn child(mut self, pt: ParseToken<'a>) -> ParseTokenBuilder<'a> {
+        match self.open.last_mut() {
+            Some((_, children)) => children.push(pt),
+            None => self.roots.push(pt)
+        }
+        self
+    }
+
+    /// Opens a new branch with the given tags inside the innermost open branch.
+    pub fn branch(mut self, tags: Vec<&'a str>) -> ParseTokenBuilder<'a> {
+        self.open.push((tags, vec![]));
+        self
+    }
+
+    /// Ends the innermost open branch. Since a branch borrows its body from
+    /// its first child, a branch without children makes the tree invalid.
+    pub fn end(mut self) -> ParseTokenBuilder<'a> {
+        match self.open.pop() {
+            Some((tags, children)) => {
+                match ParseToken::try_new_branch_from_first(children, tags) {
+                    Some(pt) => self.child(pt),
+                    None => {
+                        self.invalid = true;
+                        self
+                    }
+                }
+            },
+            None => {
+                self.invalid = true;
+                self
+            }
+        }
+    }
+
+    /// Ends any branches still open and returns the finished tree. Returns None
+    /// if the tree is invalid: if nothing was added, if more than one
+    /// top-level token was added, if a branch ended without children, or if
+    /// [ParseTokenBuilder::end] was called with no branch open.
+    pub fn build(mut self) -> Option<ParseToken<'a>> {
+        while !self.open.is_empty() {
+            self = self.end();
+        }
+        if !self.invalid && self.roots.len() == 1 {
+            self.roots.pop()
+        } else {
+            None
+        }
+    }
+}
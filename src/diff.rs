@@ -0,0 +1,62 @@
+use super::parse_token::*;
+
+/// One difference between two trees, found by [ParseToken::diff]. Each path is
+/// the sequence of child indices leading from the root to the token that
+/// differs; the root's path is empty.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TreeDiff {
+    /// The token has different tags.
+    TagsChanged { path: Vec<usize>, before: Vec<String>, after: Vec<String> },
+    /// The token is a leaf in both trees, with different text.
+    TextChanged { path: Vec<usize>, before: String, after: String },
+    /// The token is a leaf in one tree and a branch in the other.
+    KindChanged { path: Vec<usize> },
+    /// The token only exists in the second tree.
+    ChildAdded { path: Vec<usize> },
+    /// The token only exists in the first tree.
+    ChildRemoved { path: Vec<usize> }
+}
+
+impl<'a> ParseToken<'a> {
+    /// Lists the differences between this tree and another, in preorder.
+    /// Children are compared position by position, so inserting a child near
+    /// the start of a branch shows up as changes to every later child.
+    pub fn diff<'b>(&self, other: &ParseToken<'b>) -> Vec<TreeDiff> {
+        let mut diffs = vec![];
+        self.diff_with(other, &mut vec![], &mut diffs);
+        diffs
+    }
+
+    fn diff_with(&self, other: &ParseToken, path: &mut Vec<usize>, diffs: &mut Vec<TreeDiff>) {
+        if self.tags != other.tags {
+            diffs.push(TreeDiff::TagsChanged {
+                path: path.clone(),
+                before: self.tags.iter().map(|t| t.to_string()).collect(),
+                after: other.tags.iter().map(|t| t.to_string()).collect()
+            });
+        }
+        match (&self.node, &other.node) {
+            (ParseNode::Leaf(_), ParseNode::Leaf(_)) => {
+                if self.content() != other.content() {
+                    diffs.push(TreeDiff::TextChanged {
+                        path: path.clone(),
+                        before: self.content().to_string(),
+                        after: other.content().to_string()
+                    });
+                }
+            },
+            (ParseNode::Branch(a), ParseNode::Branch(b)) => {
+                for i in 0..a.len().max(b.len()) {
+                    path.push(i);
+                    match (a.get(i), b.get(i)) {
+                        (Some(x), Some(y)) => x.diff_with(y, path, diffs),
+                        (Some(_), None) => diffs.push(TreeDiff::ChildRemoved { path: path.clone() }),
+                        (None, _) => diffs.push(TreeDiff::ChildAdded { path: path.clone() })
+                    }
+                    path.pop();
+                }
+            },
+            _ => diffs.push(TreeDiff::KindChanged { path: path.clone() })
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use super::parse_token::*;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+/// An error found while reading input, with the byte range of the input where
+/// it was found and, if the error is about a particular parse token, that
+/// token.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError<'a> {
+    pub message: String,
+    pub span: Range<usize>,
+    pub token: Option<ParseToken<'a>>
+}
+
+impl<'a> ParseError<'a> {
+    pub fn new<M: Into<String>>(message: M, span: Range<usize>) -> ParseError<'a> {
+        ParseError { message: message.into(), span, token: None }
+    }
+
+    /// Creates an error about a parse token, spanning its content.
+    pub fn at_token<M: Into<String>>(message: M, pt: ParseToken<'a>) -> ParseError<'a> {
+        let span = pt.content_range().unwrap_or(0..0);
+        ParseError { message: message.into(), span, token: Some(pt) }
+    }
+}
+
+impl fmt::Display for ParseError<'_> {
+    /// Writes the message and the span, along with the text of the offending
+    /// token if there is one, as in `unclosed "(" at 0..1: "("`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.message, self.span.start, self.span.end)?;
+        if let Some(pt) = &self.token {
+            write!(f, ": {:?}", pt.content())?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseError<'_> {}
+
+/// The reasons applying a set of parse rules can fail.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RuleError {
+    /// The rules kept changing the parse tokens, either past the iteration
+    /// limit or in a cycle. `rule_index` is the rule that made the last change.
+    NonTerminating { rule_index: usize, iterations: usize }
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleError::NonTerminating { rule_index, iterations } => {
+                write!(f, "rule {} did not terminate after {} replacements", rule_index, iterations)
+            }
+        }
+    }
+}
+
+impl Error for RuleError {}
+
+/// The reasons a structural edit of a parse token can fail.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EditError {
+    /// Children can only be added to a branch.
+    NotABranch,
+    /// The index is past the end of the branch's children.
+    OutOfRange { index: usize, len: usize }
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::NotABranch => write!(f, "cannot edit the children of a leaf"),
+            EditError::OutOfRange { index, len } => {
+                write!(f, "child index {} is out of range for a branch with {} children", index, len)
+            }
+        }
+    }
+}
+
+impl Error for EditError {}
@@ -0,0 +1,221 @@
+use super::parse_token::*;
+use std::fmt::{self, Write};
+use std::io;
+
+/// Writes a string slice as a quoted JSON string, escaping quotes, backslashes,
+/// and control characters.
+fn write_json_str<W: Write>(s: &str, w: &mut W) -> fmt::Result {
+    w.write_char('"')?;
+    for ch in s.chars() {
+        match ch {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?
+        }
+    }
+    w.write_char('"')
+}
+
+/// Writes a string slice as a quoted Graphviz label.
+fn write_dot_str<W: Write>(s: &str, w: &mut W) -> fmt::Result {
+    w.write_char('"')?;
+    for ch in s.chars() {
+        match ch {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => {},
+            c => w.write_char(c)?
+        }
+    }
+    w.write_char('"')
+}
+
+/// Writes a string slice with XML's special characters escaped, so that it
+/// can be used as text or as a quoted attribute value.
+fn write_xml_str<W: Write>(s: &str, w: &mut W) -> fmt::Result {
+    for ch in s.chars() {
+        match ch {
+            '<' => w.write_str("&lt;")?,
+            '>' => w.write_str("&gt;")?,
+            '&' => w.write_str("&amp;")?,
+            '"' => w.write_str("&quot;")?,
+            '\'' => w.write_str("&apos;")?,
+            c => w.write_char(c)?
+        }
+    }
+    Ok(())
+}
+
+/// Lets the string writers in this module write to an [io::Write], keeping
+/// the first I/O error so it can be returned instead of a bare [fmt::Error].
+struct IoAdapter<'w, W: io::Write> {
+    inner: &'w mut W,
+    error: Option<io::Error>
+}
+
+impl<W: io::Write> Write for IoAdapter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+impl<'a> ParseToken<'a> {
+    /// Renders this tree as a JSON document. Branches become
+    /// `{"tags": [...], "content": "...", "children": [...]}` and leaves become
+    /// `{"tags": [...], "text": "..."}`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        // writing to a String never fails
+        self.write_json_fmt(&mut out).unwrap();
+        out
+    }
+
+    /// Writes the same document as [ParseToken::to_json] to a writer, piece by
+    /// piece, without building it in memory first.
+    pub fn write_json<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut adapter = IoAdapter { inner: w, error: None };
+        if self.write_json_fmt(&mut adapter).is_err() {
+            // the adapter only fails when writing fails, so there is an error
+            return Err(adapter.error.unwrap());
+        }
+        w.flush()
+    }
+
+    fn write_json_fmt<W: Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str("{\"tags\": [")?;
+        for (i, tag) in self.tags.iter().enumerate() {
+            if i > 0 {
+                w.write_str(", ")?;
+            }
+            write_json_str(tag, w)?;
+        }
+        w.write_str("], ")?;
+        match &self.node {
+            ParseNode::Leaf(_) => {
+                w.write_str("\"text\": ")?;
+                write_json_str(self.content(), w)?;
+            },
+            ParseNode::Branch(children) => {
+                w.write_str("\"content\": ")?;
+                write_json_str(self.content(), w)?;
+                w.write_str(", \"children\": [")?;
+                for (i, child) in children.iter().enumerate() {
+                    if i > 0 {
+                        w.write_str(", ")?;
+                    }
+                    child.write_json_fmt(w)?;
+                }
+                w.write_char(']')?;
+            }
+        }
+        w.write_char('}')
+    }
+
+    /// Renders this tree as XML. Branches become `<node tags="...">` elements
+    /// containing their children, and leaves become `<leaf tags="...">`
+    /// elements containing their text. Tags are separated by spaces.
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        // writing to a String never fails
+        self.write_xml_fmt(&mut out).unwrap();
+        out
+    }
+
+    fn write_xml_fmt<W: Write>(&self, w: &mut W) -> fmt::Result {
+        let name = if self.is_leaf() { "leaf" } else { "node" };
+        write!(w, "<{} tags=\"", name)?;
+        write_xml_str(&self.tags_joined(" "), w)?;
+        w.write_str("\">")?;
+        match &self.node {
+            ParseNode::Leaf(_) => write_xml_str(self.content(), w)?,
+            ParseNode::Branch(children) => {
+                for child in children {
+                    child.write_xml_fmt(w)?;
+                }
+            }
+        }
+        write!(w, "</{}>", name)
+    }
+
+    /// Renders this tree as a Graphviz `digraph`, with one node per parse token
+    /// and an edge from each branch to each of its children. Nodes are labeled
+    /// with their tags and, for leaves, their content.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph {\n");
+        let mut next_id = 0;
+        // writing to a String never fails
+        self.write_dot_nodes(&mut out, &mut next_id).unwrap();
+        out.push_str("}\n");
+        out
+    }
+
+    /// Writes this token and its descendants as DOT nodes and edges, numbering
+    /// them in preorder starting from `next_id`.
+    fn write_dot_nodes<W: Write>(&self, w: &mut W, next_id: &mut usize) -> fmt::Result {
+        let id = *next_id;
+        *next_id += 1;
+
+        let mut label = self.tags_joined("; ");
+        if self.is_leaf() {
+            label = format!("{}: {}", label, self.content());
+        }
+        write!(w, "    n{} [label=", id)?;
+        write_dot_str(&label, w)?;
+        w.write_str("];\n")?;
+
+        if let ParseNode::Branch(children) = &self.node {
+            for child in children {
+                writeln!(w, "    n{} -> n{};", id, *next_id)?;
+                child.write_dot_nodes(w, next_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders this tree as an S-expression. A leaf is rendered as its content
+    /// and a branch as `(head child1 child2 ...)`, where the head is the
+    /// branch's first tag. Branches without tags are rendered without a head.
+    pub fn to_sexpr(&self) -> String {
+        match &self.node {
+            ParseNode::Leaf(_) => self.content().to_string(),
+            ParseNode::Branch(children) => {
+                let parts: Vec<String> = self.first_tag()
+                    .map(|t| t.to_string())
+                    .into_iter()
+                    .chain(children.iter().map(|c| c.to_sexpr()))
+                    .collect();
+                format!("({})", parts.join(" "))
+            }
+        }
+    }
+
+    /// Renders this tree on a single line, for log lines and assertion
+    /// messages. Each token is labeled with its first tag; a leaf is written as
+    /// `tag:text` and a branch as `tag[child1 child2 ...]`. Line breaks and
+    /// tabs in leaf text are escaped.
+    pub fn to_compact(&self) -> String {
+        let head = self.first_tag().unwrap_or_default();
+        match &self.node {
+            ParseNode::Leaf(_) => {
+                let text = self.content()
+                    .replace('\\', "\\\\")
+                    .replace('\n', "\\n")
+                    .replace('\r', "\\r")
+                    .replace('\t', "\\t");
+                format!("{}:{}", head, text)
+            },
+            ParseNode::Branch(children) => {
+                let parts: Vec<String> = children.iter().map(|c| c.to_compact()).collect();
+                format!("{}[{}]", head, parts.join(" "))
+            }
+        }
+    }
+}
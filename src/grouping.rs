@@ -0,0 +1,140 @@
+use super::error::*;
+use super::parse_token::*;
+
+/// Finds balanced pairs of brackets (parse tokens tagged `open_tag` and
+/// `close_tag`) and wraps the tokens between each pair into a branch with the
+/// given tags, dropping the brackets themselves. Nested pairs become nested
+/// branches. Works iteratively, so deeply nested input can't overflow the call
+/// stack. Other tokens, such as whitespace leaves, are never treated as
+/// brackets and are kept as children of the group around them.
+///
+/// If the brackets are unbalanced, returns an error pointing at the first
+/// close bracket without an open bracket, or else at the first open bracket
+/// that is never closed.
+pub fn match_brackets<'a>(pts: Vec<ParseToken<'a>>, open_tag: &str, close_tag: &str, wrap_tags: Vec<&'a str>) -> Result<Vec<ParseToken<'a>>, ParseError<'a>> {
+    match_bracket_pairs(pts, &[(open_tag, close_tag, wrap_tags)])
+}
+
+/// Like [match_brackets], but matches several kinds of brackets at once. Each
+/// pair is given as `(open_tag, close_tag, wrap_tags)`, and the tokens between
+/// a matched pair are wrapped into a branch with that pair's tags. If a token
+/// has the tags of more than one pair, the first pair wins.
+///
+/// Besides the errors from [match_brackets], returns an error pointing at a
+/// close bracket that doesn't match the most recent open bracket, as in `( ]`.
+pub fn match_bracket_pairs<'a>(pts: Vec<ParseToken<'a>>, pairs: &[(&str, &str, Vec<&'a str>)]) -> Result<Vec<ParseToken<'a>>, ParseError<'a>> {
+    check_brackets(&pts, pairs)?;
+
+    // each open bracket suspends the tokens collected so far, along with the
+    // bracket's body in case the group turns out to be empty
+    let mut suspended: Vec<(Vec<ParseToken<'a>>, &'a str)> = vec![];
+    let mut current: Vec<ParseToken<'a>> = vec![];
+    for pt in pts {
+        match bracket_kind(&pt, pairs) {
+            Some(Bracket::Open(_)) => suspended.push((std::mem::take(&mut current), pt.body)),
+            Some(Bracket::Close(i)) => {
+                // balance was checked above, so there is always a group to close
+                let (outer, body) = suspended.pop().unwrap();
+                let children = std::mem::replace(&mut current, outer);
+                current.push(ParseToken::new_branch(children, body, pairs[i].2.clone()));
+            },
+            None => current.push(pt)
+        }
+    }
+    Ok(current)
+}
+
+/// Wraps each maximal run of consecutive leaves tagged `tag` into a branch with
+/// the given tags, for example to group digits into numbers. Other tokens,
+/// including branches with the tag, are passed through untouched.
+pub fn group_runs<'a>(pts: Vec<ParseToken<'a>>, tag: &str, wrap_tags: Vec<&'a str>) -> Vec<ParseToken<'a>> {
+    let mut grouped: Vec<ParseToken<'a>> = vec![];
+    let mut run: Vec<ParseToken<'a>> = vec![];
+    for pt in pts {
+        if pt.is_leaf() && pt.has_tag(tag) {
+            run.push(pt);
+            continue;
+        }
+        if !run.is_empty() {
+            grouped.push(ParseToken::new_branch_from_first(std::mem::take(&mut run), wrap_tags.clone()));
+        }
+        grouped.push(pt);
+    }
+    if !run.is_empty() {
+        grouped.push(ParseToken::new_branch_from_first(run, wrap_tags));
+    }
+    grouped
+}
+
+/// Wraps each run of consecutive parse tokens whose first tags are `pattern`,
+/// in order, into a branch with the given tags. Matches are found from left to
+/// right and can't overlap; the tokens of a match aren't searched again. An
+/// empty pattern matches nothing.
+pub fn wrap_tag_sequence<'a>(pts: Vec<ParseToken<'a>>, pattern: &[&str], wrap_tags: Vec<&'a str>) -> Vec<ParseToken<'a>> {
+    if pattern.is_empty() {
+        return pts;
+    }
+    let matches = |window: &[ParseToken]| window.iter()
+        .zip(pattern)
+        .all(|(pt, tag)| pt.first_tag() == Some(*tag));
+
+    // find where each match starts before taking the tokens apart
+    let mut starts = vec![];
+    let mut i = 0;
+    while i + pattern.len() <= pts.len() {
+        if matches(&pts[i..i + pattern.len()]) {
+            starts.push(i);
+            i += pattern.len();
+        } else {
+            i += 1;
+        }
+    }
+
+    let mut wrapped: Vec<ParseToken<'a>> = vec![];
+    let mut rest = pts.into_iter();
+    let mut pos = 0;
+    for start in starts {
+        wrapped.extend(rest.by_ref().take(start - pos));
+        let group: Vec<ParseToken<'a>> = rest.by_ref().take(pattern.len()).collect();
+        wrapped.push(ParseToken::new_branch_from_first(group, wrap_tags.clone()));
+        pos = start + pattern.len();
+    }
+    wrapped.extend(rest);
+    wrapped
+}
+
+/// Whether a token opens or closes a bracket, with the index of its pair.
+enum Bracket {
+    Open(usize),
+    Close(usize)
+}
+
+/// Which of the pairs a token opens or closes, if any.
+fn bracket_kind(pt: &ParseToken, pairs: &[(&str, &str, Vec<&str>)]) -> Option<Bracket> {
+    pairs.iter().position(|(open, _, _)| pt.has_tag(open)).map(Bracket::Open)
+        .or_else(|| pairs.iter().position(|(_, close, _)| pt.has_tag(close)).map(Bracket::Close))
+}
+
+/// Checks that every open bracket in a vector of parse tokens is followed by a
+/// matching close bracket, and vice versa.
+fn check_brackets<'a>(pts: &[ParseToken<'a>], pairs: &[(&str, &str, Vec<&str>)]) -> Result<(), ParseError<'a>> {
+    let mut open: Vec<(&ParseToken<'a>, usize)> = vec![];
+    for pt in pts {
+        match bracket_kind(pt, pairs) {
+            Some(Bracket::Open(i)) => open.push((pt, i)),
+            Some(Bracket::Close(i)) => match open.pop() {
+                Some((_, j)) if i == j => {},
+                Some((_, j)) => {
+                    let message = format!("mismatched {:?}, expected {:?}", pairs[i].1, pairs[j].1);
+                    return Err(ParseError::at_token(message, pt.clone()));
+                },
+                None => return Err(ParseError::at_token(format!("unmatched {:?}", pairs[i].1), pt.clone()))
+            },
+            None => {}
+        }
+    }
+    match open.first() {
+        Some((pt, i)) => Err(ParseError::at_token(format!("unclosed {:?}", pairs[*i].0), (*pt).clone())),
+        None => Ok(())
+    }
+}
@@ -0,0 +1,402 @@
+use super::error::*;
+use super::owned::*;
+use std::ops::Range;
+
+/// A JSON value, as much as [from_json] needs to know about it.
+enum Value {
+    Null,
+    Bool,
+    Number,
+    Str(String),
+    Array(Vec<(Value, Range<usize>)>),
+    Object(Vec<(String, Value, Range<usize>)>)
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool => "a boolean",
+            Value::Number => "a number",
+            Value::Str(_) => "a string",
+            Value::Array(_) => "an array",
+            Value::Object(_) => "an object"
+        }
+    }
+}
+
+/// How deeply arrays and objects can be nested in the input to [from_json].
+/// Each level of a tree takes two (an object and its `"children"` array), so
+/// this allows trees 1024 levels deep. Parsing is iterative, but dropping a
+/// tree is not, so without a limit, deeply nested input could still overflow
+/// the stack.
+pub const MAX_JSON_DEPTH: usize = 2048;
+
+/// An array or object whose items are still being parsed by
+/// [JsonParser::value], with the index where it starts.
+enum Container {
+    Array(usize, Vec<(Value, Range<usize>)>),
+    /// An object, with the name of the field whose value comes next.
+    Object(usize, Vec<(String, Value, Range<usize>)>, String)
+}
+
+struct JsonParser<'s> {
+    src: &'s str,
+    pos: usize
+}
+
+impl<'s> JsonParser<'s> {
+    fn error<T>(&self, message: String, span: Range<usize>) -> Result<T, ParseError<'static>> {
+        Err(ParseError::new(message, span))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// Describes whatever is at the current position, for error messages.
+    fn found(&self) -> String {
+        match self.src[self.pos..].chars().next() {
+            Some(c) => format!("{:?}", c),
+            None => "the end of the input".to_string()
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), ParseError<'static>> {
+        self.skip_whitespace();
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(format!("expected {:?}, found {}", byte as char, self.found()), self.pos..self.pos + 1)
+        }
+    }
+
+    /// Parses one value and everything nested in it. Arrays and objects are
+    /// kept on an explicit stack rather than parsed recursively.
+    fn value(&mut self) -> Result<(Value, Range<usize>), ParseError<'static>> {
+        let mut stack: Vec<Container> = vec![];
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            if let Some(b'{' | b'[') = self.peek() {
+                if stack.len() >= MAX_JSON_DEPTH {
+                    return self.error(format!("nested more than {} levels deep", MAX_JSON_DEPTH), start..start + 1);
+                }
+            }
+            let mut done = match self.peek() {
+                Some(b'{') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'}') {
+                        let name = self.field_name()?;
+                        stack.push(Container::Object(start, vec![], name));
+                        continue;
+                    }
+                    self.pos += 1;
+                    (Value::Object(vec![]), start..self.pos)
+                },
+                Some(b'[') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    if self.peek() != Some(b']') {
+                        stack.push(Container::Array(start, vec![]));
+                        continue;
+                    }
+                    self.pos += 1;
+                    (Value::Array(vec![]), start..self.pos)
+                },
+                Some(b'"') => (Value::Str(self.string()?), start..self.pos),
+                Some(b'-' | b'0'..=b'9') => (self.number()?, start..self.pos),
+                _ => (self.literal()?, start..self.pos)
+            };
+            // add the finished value to its container, then close every
+            // container that ends right after it
+            loop {
+                let Some(top) = stack.last_mut() else {
+                    return Ok(done);
+                };
+                match top {
+                    Container::Array(_, items) => items.push(done),
+                    Container::Object(_, fields, name) => fields.push((std::mem::take(name), done.0, done.1))
+                }
+                self.skip_whitespace();
+                match (self.peek(), top) {
+                    (Some(b','), Container::Array(..)) => {
+                        self.pos += 1;
+                        break;
+                    },
+                    (Some(b','), Container::Object(_, _, name)) => {
+                        self.pos += 1;
+                        *name = self.field_name()?;
+                        break;
+                    },
+                    (Some(b']'), Container::Array(..)) | (Some(b'}'), Container::Object(..)) => {
+                        self.pos += 1;
+                        // the stack can't be empty, since `top` is on it
+                        done = match stack.pop().unwrap() {
+                            Container::Array(start, items) => (Value::Array(items), start..self.pos),
+                            Container::Object(start, fields, _) => (Value::Object(fields), start..self.pos)
+                        };
+                    },
+                    (_, Container::Array(..)) => {
+                        return self.error(format!("expected ',' or ']', found {}", self.found()), self.pos..self.pos + 1);
+                    },
+                    (_, Container::Object(..)) => {
+                        return self.error(format!("expected ',' or '}}', found {}", self.found()), self.pos..self.pos + 1);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parses the name of a field in an object and the colon after it.
+    fn field_name(&mut self) -> Result<String, ParseError<'static>> {
+        self.skip_whitespace();
+        if self.peek() != Some(b'"') {
+            return self.error(format!("expected a field name, found {}", self.found()), self.pos..self.pos + 1);
+        }
+        let name = self.string()?;
+        self.expect(b':')?;
+        Ok(name)
+    }
+
+    /// Checks the syntax of a number: an optional minus sign, an integer part
+    /// without leading zeros, then an optional fraction and exponent.
+    fn number(&mut self) -> Result<Value, ParseError<'static>> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => {
+                self.digits();
+            },
+            _ => return self.error("invalid number".to_string(), start..self.pos + 1)
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !self.digits() {
+                return self.error("invalid number".to_string(), start..self.pos + 1);
+            }
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if !self.digits() {
+                return self.error("invalid number".to_string(), start..self.pos + 1);
+            }
+        }
+        Ok(Value::Number)
+    }
+
+    /// Skips a run of digits, returning whether there were any.
+    fn digits(&mut self) -> bool {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn literal(&mut self) -> Result<Value, ParseError<'static>> {
+        for (word, value) in [("null", Value::Null), ("true", Value::Bool), ("false", Value::Bool)] {
+            if self.src[self.pos..].starts_with(word) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        self.error(format!("expected a value, found {}", self.found()), self.pos..self.pos + 1)
+    }
+
+    fn string(&mut self) -> Result<String, ParseError<'static>> {
+        let start = self.pos;
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            let mut chars = self.src[self.pos..].chars();
+            match chars.next() {
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                },
+                Some('\\') => {
+                    let escape_start = self.pos;
+                    self.pos += 1;
+                    let c = match chars.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            self.pos += 1;
+                            let mut code = self.hex4()?;
+                            if (0xD800..0xDC00).contains(&code) && self.src[self.pos..].starts_with("\\u") {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if !(0xDC00..=0xDFFF).contains(&low) {
+                                    return self.error("invalid unicode escape".to_string(), escape_start..self.pos);
+                                }
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            match char::from_u32(code) {
+                                Some(c) => {
+                                    out.push(c);
+                                    continue;
+                                },
+                                None => return self.error("invalid unicode escape".to_string(), escape_start..self.pos)
+                            }
+                        },
+                        _ => return self.error("invalid escape".to_string(), escape_start..self.pos + 1)
+                    };
+                    self.pos += 1;
+                    out.push(c);
+                },
+                Some(c) if (c as u32) < 0x20 => {
+                    return self.error("unescaped control character in string".to_string(), self.pos..self.pos + 1);
+                },
+                Some(c) => {
+                    self.pos += c.len_utf8();
+                    out.push(c);
+                },
+                None => return self.error("unterminated string".to_string(), start..self.pos)
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError<'static>> {
+        let digits = self.src.get(self.pos..self.pos + 4).unwrap_or_default();
+        match u32::from_str_radix(digits, 16) {
+            Ok(code) if digits.len() == 4 && digits.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                self.pos += 4;
+                Ok(code)
+            },
+            _ => self.error("expected four hex digits".to_string(), self.pos..self.pos + digits.len())
+        }
+    }
+}
+
+/// Takes a field out of a JSON object, if it is there.
+fn take_field(fields: &mut Vec<(String, Value, Range<usize>)>, name: &str) -> Option<(Value, Range<usize>)> {
+    let i = fields.iter().position(|(n, _, _)| n == name)?;
+    let (_, value, span) = fields.remove(i);
+    Some((value, span))
+}
+
+fn expect_string(value: Value, span: Range<usize>, what: &str) -> Result<String, ParseError<'static>> {
+    match value {
+        Value::Str(s) => Ok(s),
+        other => Err(ParseError::new(format!("expected {} to be a string, found {}", what, other.kind()), span))
+    }
+}
+
+/// A token read by [start_token]: either a finished leaf, or a branch whose
+/// children haven't been read yet.
+enum Started {
+    Leaf(ParseTokenOwned),
+    Branch(PendingBranch)
+}
+
+/// A branch being built by [token_from_value].
+struct PendingBranch {
+    tags: Vec<String>,
+    content: String,
+    children: std::vec::IntoIter<(Value, Range<usize>)>,
+    done: Vec<ParseTokenOwned>
+}
+
+/// Reads the fields of one token object, without reading its children.
+fn start_token(value: Value, span: Range<usize>) -> Result<Started, ParseError<'static>> {
+    let mut fields = match value {
+        Value::Object(fields) => fields,
+        other => return Err(ParseError::new(format!("expected a token object, found {}", other.kind()), span))
+    };
+    let tags = match take_field(&mut fields, "tags") {
+        Some((Value::Array(items), _)) => items.into_iter()
+            .map(|(tag, span)| expect_string(tag, span, "a tag"))
+            .collect::<Result<Vec<String>, ParseError>>()?,
+        Some((other, span)) => {
+            return Err(ParseError::new(format!("expected \"tags\" to be an array, found {}", other.kind()), span));
+        },
+        None => return Err(ParseError::new("missing field \"tags\"", span))
+    };
+    match take_field(&mut fields, "children") {
+        Some((Value::Array(items), _)) => {
+            let content = match take_field(&mut fields, "content") {
+                Some((value, span)) => expect_string(value, span, "\"content\"")?,
+                None => return Err(ParseError::new("missing field \"content\" of a branch", span))
+            };
+            let done = Vec::with_capacity(items.len());
+            Ok(Started::Branch(PendingBranch { tags, content, children: items.into_iter(), done }))
+        },
+        Some((other, span)) => {
+            Err(ParseError::new(format!("expected \"children\" to be an array, found {}", other.kind()), span))
+        },
+        None => {
+            let content = match take_field(&mut fields, "text") {
+                Some((value, span)) => expect_string(value, span, "\"text\"")?,
+                None => return Err(ParseError::new("missing field \"text\" or \"children\"", span))
+            };
+            Ok(Started::Leaf(ParseTokenOwned { node: ParseNodeOwned::Leaf, content, tags }))
+        }
+    }
+}
+
+/// Converts a parsed token object into a tree, keeping the branches still
+/// being built on an explicit stack rather than recursing.
+fn token_from_value(value: Value, span: Range<usize>) -> Result<ParseTokenOwned, ParseError<'static>> {
+    let mut stack: Vec<PendingBranch> = vec![];
+    let mut next = Some((value, span));
+    loop {
+        if let Some((value, span)) = next.take() {
+            match start_token(value, span)? {
+                Started::Branch(pending) => stack.push(pending),
+                Started::Leaf(pt) => match stack.last_mut() {
+                    Some(parent) => parent.done.push(pt),
+                    None => return Ok(pt)
+                }
+            }
+        }
+        // a leaf at the top level has already been returned, so the stack
+        // can't be empty here
+        let top = stack.last_mut().unwrap();
+        match top.children.next() {
+            Some(child) => next = Some(child),
+            None => {
+                let PendingBranch { tags, content, done, .. } = stack.pop().unwrap();
+                let pt = ParseTokenOwned { node: ParseNodeOwned::Branch(done), content, tags };
+                match stack.last_mut() {
+                    Some(parent) => parent.done.push(pt),
+                    None => return Ok(pt)
+                }
+            }
+        }
+    }
+}
+
+/// Reads a tree written by [ParseToken::to_json](super::parse_token::ParseToken::to_json)
+/// back into an owned tree. Fields other than the ones `to_json` writes are
+/// ignored. Input nested more than [MAX_JSON_DEPTH] levels deep is rejected.
+/// On failure, the error's span is the byte range of the offending part of `s`.
+pub fn from_json(s: &str) -> Result<ParseTokenOwned, ParseError<'static>> {
+    let mut parser = JsonParser { src: s, pos: 0 };
+    let (value, span) = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < s.len() {
+        return parser.error(format!("unexpected {} after the document", parser.found()), parser.pos..s.len());
+    }
+    token_from_value(value, span)
+}
@@ -1,18 +1,226 @@
+#[macro_use]
+mod macros;
 pub mod parse_token;
+pub mod traverse;
+pub mod query;
+pub mod export;
+pub mod rewrite;
+pub mod grouping;
+pub mod rules;
+pub mod pratt;
+pub mod owned;
+pub mod builder;
+pub mod position;
+pub mod visit;
+pub mod zipper;
+pub mod tree_index;
+pub mod arena;
+pub mod error;
+pub mod import;
+pub mod diff;
+pub mod stream;
+pub mod sexpr;
 #[cfg(test)]
 pub mod lispy_tests;
 pub use blex::*;
+use parse_token::*;
+use error::*;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Whether two tokens are the same down to their leaf ranges and bodies. Unlike
+/// `==`, which compares content, this notices a rule moving a leaf to another
+/// range with the same text, or re-borrowing it from another body.
+fn same_token(a: &ParseToken, b: &ParseToken) -> bool {
+    if a.tags != b.tags || !std::ptr::eq(a.body, b.body) {
+        return false;
+    }
+    match (&a.node, &b.node) {
+        (ParseNode::Leaf(x), ParseNode::Leaf(y)) => x == y,
+        (ParseNode::Branch(x), ParseNode::Branch(y)) => same_tokens(x, y),
+        _ => false
+    }
+}
+
+fn same_tokens(a: &[ParseToken], b: &[ParseToken]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| same_token(x, y))
+}
+
+/// Applies a rule across the parse tokens, calling `on_change` with the start
+/// index, the window that was replaced, and its replacement, each time a
+/// replacement changes the parse tokens. Stops early if `on_change` returns
+/// false. Returns whether anything changed.
+fn apply_parse_rule<'a, F, C>(rule: &F, pts: &mut Vec<ParseToken<'a>>, on_change: &mut C) -> bool
+where
+    F: Fn(Vec<ParseToken<'a>>) -> Option<Vec<ParseToken<'a>>>,
+    C: FnMut(usize, &[ParseToken<'a>], &[ParseToken<'a>]) -> bool
+{
+    let mut changed = false;
+    // iterate through each starting position in the parse tokens
+    let mut start_index: usize = 0;
+    'current_start: while start_index < pts.len() {
+        // the end_index will increment if the rule returns None
+        let mut end_index: usize = start_index + 1;
+        let mut applied = rule(pts[start_index..end_index].to_vec());
+
+        // if the rule returns Some or requests tokens past the end, finish the iteration
+        while applied.is_none() {
+            end_index += 1;
+            if end_index > pts.len() {
+                start_index += 1;
+                continue 'current_start;
+            }
+            applied = rule(pts[start_index..end_index].to_vec());
+        }
+
+        // we know that the returned parse tokens will exist at this point, so unwrap() is safe
+        let replacement = applied.unwrap();
+        if same_tokens(&replacement, &pts[start_index..end_index]) {
+            start_index += 1;
+            continue;
+        }
+        changed = true;
+        let go_on = on_change(start_index, &pts[start_index..end_index], &replacement);
+
+        let r_len = replacement.len();
+
+        // insert the new parse tokens where the old ones were
+        pts.splice(start_index..end_index, replacement);
+        if !go_on {
+            break;
+        }
+        if r_len >= end_index - start_index {
+            start_index += 1;
+        }
+    }
+    changed
+}
+
+/// See [process_parse_rule]. May or may not print a message signifying when
+/// each replacement is made. Returns whether any replacement changed the
+/// parse tokens.
+pub fn process_parse_rule_maybe_verbose(rule: impl Fn(Vec<ParseToken>) -> Option<Vec<ParseToken>>, pts: &mut Vec<ParseToken>, verbose: bool) -> bool {
+    apply_parse_rule(&rule, pts, &mut |_, window, replacement| {
+        if verbose {
+            println!("\nReplacing");
+            print_parse_tokens(window.to_vec());
+            println!("with");
+            print_parse_tokens(replacement.to_vec());
+        }
+        true
+    })
+}
+
+/// Processes a rule across a vector of parse tokens in the same way that
+/// [process_rule] processes a rule across a vector of tokens.
+pub fn process_parse_rule(rule: impl Fn(Vec<ParseToken>) -> Option<Vec<ParseToken>>, pts: &mut Vec<ParseToken>) {
+    process_parse_rule_maybe_verbose(rule, pts, false);
+}
+
+/// Processes multiple rules on a vector of parse tokens. See
+/// [process_parse_rule]. Unlike [process_rules], the rules are applied again
+/// and again, in order, until none of them changes the parse tokens, so rules
+/// can build trees one level at a time.
+pub fn process_parse_rules<F: Fn(Vec<ParseToken>) -> Option<Vec<ParseToken>>>(rules: Vec<F>, pts: &mut Vec<ParseToken>, verbose: bool) {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in &rules {
+            changed |= process_parse_rule_maybe_verbose(rule, pts, verbose);
+        }
+    }
+}
+
+/// A record of one replacement made by [process_parse_rules_traced].
+#[derive(Clone)]
+pub struct RuleTrace<'a> {
+    /// The position of the rule that made the replacement.
+    pub rule_index: usize,
+    /// Where the replaced window started in the parse tokens at the time.
+    pub start: usize,
+    /// The parse tokens that were replaced.
+    pub input: Vec<ParseToken<'a>>,
+    /// The parse tokens that replaced them.
+    pub output: Vec<ParseToken<'a>>
+}
+
+/// See [process_parse_rules]. Returns a trace of every replacement that changed
+/// the parse tokens, in the order they were made, which shows which rule fired
+/// on which window (for example, to find a rule that keeps firing).
+pub fn process_parse_rules_traced<'a, F: Fn(Vec<ParseToken<'a>>) -> Option<Vec<ParseToken<'a>>>>(rules: Vec<F>, pts: &mut Vec<ParseToken<'a>>) -> Vec<RuleTrace<'a>> {
+    let mut traces = vec![];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (rule_index, rule) in rules.iter().enumerate() {
+            changed |= apply_parse_rule(rule, pts, &mut |start, window, replacement| {
+                traces.push(RuleTrace { rule_index, start, input: window.to_vec(), output: replacement.to_vec() });
+                true
+            });
+        }
+    }
+    traces
+}
+
+/// See [process_parse_rules]. Instead of looping forever on rules that never
+/// settle, returns [RuleError::NonTerminating] once more than `max_iterations`
+/// replacements have been made, or as soon as a full pass over the rules leaves
+/// the parse tokens as they were after an earlier pass (a cycle). The parse
+/// tokens are left as they were when the error was detected.
+pub fn try_process_parse_rules<'a, F: Fn(Vec<ParseToken<'a>>) -> Option<Vec<ParseToken<'a>>>>(rules: Vec<F>, pts: &mut Vec<ParseToken<'a>>, max_iterations: usize) -> Result<(), RuleError> {
+    let hash_of = |pts: &Vec<ParseToken>| {
+        let mut hasher = DefaultHasher::new();
+        pts.hash(&mut hasher);
+        hasher.finish()
+    };
+    // the parse tokens after each pass, grouped by hash; a hash can collide, so
+    // a cycle is only reported when the tokens themselves are equal
+    let mut seen: HashMap<u64, Vec<Vec<ParseToken<'a>>>> = HashMap::new();
+    seen.entry(hash_of(pts)).or_default().push(pts.clone());
+    let mut iterations = 0;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut last_rule = 0;
+        for (rule_index, rule) in rules.iter().enumerate() {
+            let rule_changed = apply_parse_rule(rule, pts, &mut |_, _, _| {
+                iterations += 1;
+                iterations <= max_iterations
+            });
+            if iterations > max_iterations {
+                return Err(RuleError::NonTerminating { rule_index, iterations });
+            }
+            if rule_changed {
+                last_rule = rule_index;
+            }
+            changed |= rule_changed;
+        }
+        if changed {
+            let states = seen.entry(hash_of(pts)).or_default();
+            if states.iter().any(|s| same_tokens(s, pts)) {
+                return Err(RuleError::NonTerminating { rule_index: last_rule, iterations });
+            }
+            states.push(pts.clone());
+        }
+    }
+    Ok(())
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use parse_token::*;
+    use grouping::*;
+    use pratt::*;
+    use builder::*;
+    use visit::*;
+    use zipper::*;
+    use tree_index::*;
+    use arena::*;
+    use import::*;
+    use diff::*;
 
-    #[test]
-    fn print_parse_tokens() {
-        let body = "34 + 35";
-
-        let tox = vec![
+    fn add_expr_tree(body: &str) -> ParseToken<'_> {
+        let tox = [
             Token{body, indices: 0..2, tags: vec!["int"]},
             Token{body, indices: 3..4, tags: vec!["oper", "plus"]},
             Token{body, indices: 5..7, tags: vec!["int"]}
@@ -24,9 +232,1416 @@ mod tests {
             ParseToken::new_leaf(tox[2].clone())
         ];
 
-        let pt : ParseToken = ParseToken::new_branch_from_first(pts, vec!["expr", "addExpr"]);
-        
+        ParseToken::new_branch_from_first(pts, vec!["expr", "addExpr"])
+    }
+
+    /// One leaf per non-whitespace character, each tagged with itself.
+    fn char_leaves(text: &str) -> Vec<ParseToken<'_>> {
+        tokens_to_parse_tokens(str_to_tokens(text)).into_iter()
+            .filter(|pt| !pt.content().trim().is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn print_parse_tokens() {
+        let pt = parse_tree!(["expr", "addExpr"] {
+            leaf("34", ["int"]),
+            leaf("+", ["oper", "plus"]),
+            leaf("35", ["int"])
+        });
+        assert_eq!(pt, add_expr_tree("34 + 35"));
+        assert_eq!(pt.content(), "34 + 35");
+        assert!(pt.to_string().starts_with("(\"expr\"; \"addExpr\"): \"34 + 35\"\n"));
+
         println!("{}", pt);
         println!("{}", pt.content());
     }
+
+    #[test]
+    fn nested_parse_tree_macro() {
+        let pt = parse_tree!(["stmt"] {
+            ["expr", "addExpr"] {
+                leaf("34", ["int"]),
+                leaf("+", ["oper", "plus"]),
+                leaf("35", ["int"]),
+            },
+            leaf(";", []),
+            [] {}
+        });
+
+        assert_eq!(pt.depth(), 3);
+        assert_eq!(pt.children().unwrap()[0], add_expr_tree("34 + 35"));
+        assert!(pt.children().unwrap()[1].tags.is_empty());
+        assert!(pt.children().unwrap()[2].is_branch());
+        assert_eq!(pt.content(), "34 + 35 ;");
+        assert_eq!(pt.children().unwrap()[1].content_range(), Some(8..9));
+    }
+
+    #[test]
+    fn children_of_branch_and_leaf() {
+        let mut pt = add_expr_tree("34 + 35");
+
+        let children = pt.children().unwrap();
+        assert_eq!(children.len(), 3);
+        assert!(children[1].has_tag("plus"));
+        assert!(children[0].children().is_none());
+
+        pt.children_mut().unwrap().pop();
+        assert_eq!(pt.content(), "34 +");
+    }
+
+    #[test]
+    fn leaf_and_branch_predicates() {
+        let pt = add_expr_tree("34 + 35");
+
+        assert!(pt.is_branch());
+        assert!(!pt.is_leaf());
+        assert!(pt.children().unwrap().iter().all(|c| c.is_leaf()));
+        assert!(empty_parse_token().is_leaf());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_to_json() {
+        let body = String::from("34 + 35");
+        let json = serde_json::to_string(&add_expr_tree(&body)).unwrap();
+        drop(body);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["content"], "34 + 35");
+        assert_eq!(value["tags"], serde_json::json!(["expr", "addExpr"]));
+        let plus = &value["node"]["Branch"][1];
+        assert_eq!(plus["node"], "Leaf");
+        assert_eq!(plus["content"], "+");
+        assert_eq!(plus["tags"], serde_json::json!(["oper", "plus"]));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let body = String::from("34 + 35");
+        let json = serde_json::to_string(&add_expr_tree(&body)).unwrap();
+        let expected = add_expr_tree(&body).to_owned();
+        drop(body);
+
+        let owned: owned::ParseTokenOwned = serde_json::from_str(&json).unwrap();
+        assert_eq!(owned, expected);
+        assert_eq!(owned.children().unwrap()[1].content(), "+");
+        assert_eq!(serde_json::to_string(&owned).unwrap(), json);
+    }
+
+    #[test]
+    fn leaves_in_order() {
+        let body = "34 + 35";
+        let mut pt = add_expr_tree(body);
+        pt = ParseToken::new_branch_from_first(
+            vec![pt, ParseToken::new_leaf(Token{body, indices: 5..7, tags: vec!["int"]})],
+            vec!["expr"]);
+
+        let contents: Vec<&str> = pt.leaves().map(|l| l.content()).collect();
+        assert_eq!(contents, vec!["34", "+", "35", "35"]);
+
+        let leaf = empty_parse_token();
+        assert_eq!(leaf.leaves().count(), 1);
+    }
+
+    #[test]
+    fn preorder_tags() {
+        let pt = add_expr_tree("34 + 35");
+
+        let tags: Vec<&str> = pt.iter_preorder().map(|t| t.tags[0].as_ref()).collect();
+        assert_eq!(tags, vec!["expr", "int", "oper", "int"]);
+    }
+
+    #[test]
+    fn postorder_root_last() {
+        let body = "34 + 35";
+        let pt = ParseToken::new_branch_from_first(
+            vec![add_expr_tree(body), ParseToken::new_leaf(Token{body, indices: 5..7, tags: vec!["int"]})],
+            vec!["stmt"]);
+
+        let tags: Vec<&str> = pt.iter_postorder().map(|t| t.tags[0].as_ref()).collect();
+        assert_eq!(tags, vec!["int", "oper", "int", "expr", "int", "stmt"]);
+    }
+
+    #[test]
+    fn tree_depth() {
+        let pt = add_expr_tree("34 + 35");
+
+        assert_eq!(pt.depth(), 2);
+        assert_eq!(pt.children().unwrap()[0].depth(), 1);
+        assert_eq!(ParseToken::new_branch(vec![], "", vec![]).depth(), 1);
+    }
+
+    #[test]
+    fn find_outermost_tag() {
+        let body = "34 + 35";
+        let inner = add_expr_tree(body);
+        let outer = ParseToken::new_branch_from_first(
+            vec![inner, ParseToken::new_leaf(Token{body, indices: 5..7, tags: vec!["int"]})],
+            vec!["addExpr"]);
+
+        assert!(std::ptr::eq(outer.find_first_by_tag("addExpr").unwrap(), &outer));
+        let first_int = outer.find_first_by_tag("int").unwrap();
+        assert!(std::ptr::eq(first_int, &outer.children().unwrap()[0].children().unwrap()[0]));
+        assert!(outer.find_first_by_tag("minus").is_none());
+    }
+
+    #[test]
+    fn find_by_predicate() {
+        let pt = add_expr_tree("34 + 35");
+        let calls = std::cell::Cell::new(0);
+
+        let plus = pt.find_by(|t| {
+            calls.set(calls.get() + 1);
+            t.is_leaf() && t.content() == "+"
+        });
+        assert!(plus.unwrap().has_tag("oper"));
+        assert_eq!(calls.get(), 3);
+
+        let three = pt.find_by(|t| t.children().is_some_and(|c| c.len() == 3));
+        assert!(std::ptr::eq(three.unwrap(), &pt));
+    }
+
+    #[test]
+    fn compare_separate_trees() {
+        let first = String::from("34 + 35");
+        let second = String::from("34 + 35");
+        let shifted = String::from("  34 + 35");
+
+        assert_eq!(add_expr_tree(&first), add_expr_tree(&second));
+
+        let moved = ParseToken::new_branch_from_first(vec![
+            ParseToken::new_leaf(Token{body: &shifted, indices: 2..4, tags: vec!["int"]}),
+            ParseToken::new_leaf(Token{body: &shifted, indices: 5..6, tags: vec!["oper", "plus"]}),
+            ParseToken::new_leaf(Token{body: &shifted, indices: 7..9, tags: vec!["int"]})
+        ], vec!["expr", "addExpr"]);
+        assert_eq!(add_expr_tree(&first), moved);
+
+        let mut retagged = add_expr_tree(&second);
+        retagged.tags.pop();
+        assert_ne!(add_expr_tree(&first), retagged);
+        assert_ne!(add_expr_tree(&first), add_expr_tree("34 - 35"));
+    }
+
+    #[test]
+    fn hash_separate_trees() {
+        let first = String::from("34 + 35");
+        let second = String::from("34 + 35");
+        let mut seen = std::collections::HashSet::new();
+
+        seen.insert(add_expr_tree(&first));
+        seen.insert(add_expr_tree(&second));
+        assert_eq!(seen.len(), 1);
+
+        seen.insert(add_expr_tree("34 - 35"));
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn json_document() {
+        let body = "34 \"+\"";
+        let pt = ParseToken::new_branch_from_first(vec![
+            ParseToken::new_leaf(Token{body, indices: 0..2, tags: vec!["int"]}),
+            ParseToken::new_leaf(Token{body, indices: 3..6, tags: vec!["str"]})
+        ], vec!["expr", "addExpr"]);
+
+        assert_eq!(pt.to_json(), concat!(
+            r#"{"tags": ["expr", "addExpr"], "content": "34 \"+\"", "children": ["#,
+            r#"{"tags": ["int"], "text": "34"}, "#,
+            r#"{"tags": ["str"], "text": "\"+\""}]}"#));
+    }
+
+    #[test]
+    fn source_of_reordered_tree() {
+        let mut pt = add_expr_tree("34 + 35");
+        assert_eq!(pt.to_source(), "34+35");
+
+        let children = pt.children_mut().unwrap();
+        children.swap(0, 2);
+        children[2].node = ParseNode::Leaf(0..1);
+        assert_eq!(pt.to_source(), "35+3");
+    }
+
+    #[test]
+    fn content_range_of_reordered_tree() {
+        let mut pt = add_expr_tree("34 + 35");
+        pt.children_mut().unwrap().reverse();
+        assert_eq!(pt.content_range(), Some(0..7));
+        assert_eq!(pt.content(), "34 + 35");
+
+        pt.children_mut().unwrap().remove(1);
+        assert_eq!(pt.content_range(), Some(0..7));
+
+        let (start, end) = (5, 3);
+        let backwards = ParseToken{node: ParseNode::Leaf(start..end), body: "34 + 35", tags: vec![]};
+        assert_eq!(backwards.content(), "");
+        assert_eq!(ParseToken::new_branch(vec![], "", vec![]).content_range(), None);
+    }
+
+    #[test]
+    fn try_branch_from_first() {
+        assert!(ParseToken::try_new_branch_from_first(vec![], vec!["expr"]).is_none());
+
+        let leaf = ParseToken::new_leaf(token_from_string("34", vec!["int"]));
+        let pt = ParseToken::try_new_branch_from_first(vec![leaf], vec!["expr"]).unwrap();
+        assert_eq!(pt.body, "34");
+        assert_eq!(pt.content(), "34");
+    }
+
+    #[test]
+    fn add_and_remove_tags() {
+        let mut pt = add_expr_tree("34 + 35");
+
+        pt.add_tag("constant");
+        pt.add_tag("constant");
+        assert_eq!(pt.tags, vec!["expr", "addExpr", "constant"]);
+        assert!(pt.has_tag("constant"));
+
+        pt.tags.push("expr".into());
+        pt.remove_tag("expr");
+        assert_eq!(pt.tags, vec!["addExpr", "constant"]);
+        assert!(!pt.has_tag("expr"));
+    }
+
+    #[test]
+    fn retag_plus_leaves() {
+        let body = "34 + 35";
+        let mut pt = ParseToken::new_branch_from_first(vec![
+            ParseToken::new_leaf(Token{body, indices: 0..2, tags: vec!["int"]}),
+            ParseToken::new_leaf(Token{body, indices: 3..4, tags: vec![]}),
+            ParseToken::new_leaf(Token{body, indices: 5..7, tags: vec!["int"]})
+        ], vec!["expr"]);
+
+        pt.retag_by(|t| if t.is_leaf() && t.content() == "+" { Some("oper") } else { None });
+        pt.retag_by(|t| if t.is_leaf() && t.content() == "+" { Some("oper") } else { None });
+
+        let opers = pt.find_all_by_tag("oper");
+        assert_eq!(opers.len(), 1);
+        assert_eq!(opers[0].tags, vec!["oper"]);
+        assert_eq!(pt.tags, vec!["expr"]);
+    }
+
+    #[test]
+    fn match_nested_brackets() {
+        let pts = match_brackets(char_leaves("a [ [ ] b ]"), "[", "]", vec!["list"]).unwrap();
+
+        assert_eq!(pts.len(), 2);
+        assert_eq!(pts[0].content(), "a");
+        let outer = pts[1].children().unwrap();
+        assert!(pts[1].has_tag("list"));
+        assert_eq!(outer.len(), 2);
+        assert_eq!(outer[0].children().unwrap().len(), 0);
+        assert!(outer[0].has_tag("list"));
+        assert_eq!(outer[1].content(), "b");
+    }
+
+    #[test]
+    fn unmatched_brackets_error() {
+        let err = match_brackets(char_leaves("( a ( b )"), "(", ")", vec!["expr"]).unwrap_err();
+        assert_eq!(err.span, 0..1);
+        assert_eq!(err.token.as_ref().map(|pt| pt.content()), Some("("));
+        assert_eq!(err.to_string(), "unclosed \"(\" at 0..1: \"(\"");
+
+        let err = match_brackets(char_leaves("a ) ("), "(", ")", vec!["expr"]).unwrap_err();
+        assert_eq!(err.message, "unmatched \")\"");
+        assert_eq!(err.span, 2..3);
+    }
+
+    fn arithmetic_table() -> PrecedenceTable {
+        let mut prec = PrecedenceTable::new();
+        prec.add("+", 1, Associativity::Left);
+        prec.add("-", 1, Associativity::Left);
+        prec.add("*", 2, Associativity::Left);
+        prec.add("^", 3, Associativity::Right);
+        prec
+    }
+
+    #[test]
+    fn precedence_mixed() {
+        let body = "34 + 35 * 2";
+        let pts = vec![
+            ParseToken::new_leaf(Token{body, indices: 0..2, tags: vec!["int"]}),
+            ParseToken::new_leaf(Token{body, indices: 3..4, tags: vec!["oper", "plus"]}),
+            ParseToken::new_leaf(Token{body, indices: 5..7, tags: vec!["int"]}),
+            ParseToken::new_leaf(Token{body, indices: 8..9, tags: vec!["oper", "times"]}),
+            ParseToken::new_leaf(Token{body, indices: 10..11, tags: vec!["int"]})
+        ];
+        let mut prec = PrecedenceTable::new();
+        prec.add("plus", 1, Associativity::Left);
+        prec.add("times", 2, Associativity::Left);
+
+        let tree = parse_precedence(pts, &prec).unwrap();
+        assert_eq!(tree.to_sexpr(), "(binExpr 34 + (binExpr 35 * 2))");
+
+        let tree = parse_precedence(char_leaves("1 * 2 + 3 ^ 4 * 5"), &arithmetic_table()).unwrap();
+        assert_eq!(tree.to_sexpr(), "(binExpr (binExpr 1 * 2) + (binExpr (binExpr 3 ^ 4) * 5))");
+    }
+
+    #[test]
+    fn precedence_associativity() {
+        let prec = arithmetic_table();
+
+        let left = parse_precedence(char_leaves("1 - 2 - 3"), &prec).unwrap();
+        assert_eq!(left.to_sexpr(), "(binExpr (binExpr 1 - 2) - 3)");
+
+        let right = parse_precedence(char_leaves("2 ^ 3 ^ 4"), &prec).unwrap();
+        assert_eq!(right.to_sexpr(), "(binExpr 2 ^ (binExpr 3 ^ 4))");
+    }
+
+    #[test]
+    fn precedence_malformed() {
+        let prec = arithmetic_table();
+
+        assert!(parse_precedence(vec![], &prec).is_none());
+        assert!(parse_precedence(char_leaves("1 +"), &prec).is_none());
+        assert!(parse_precedence(char_leaves("+ 1"), &prec).is_none());
+        assert!(parse_precedence(char_leaves("1 2"), &prec).is_none());
+        assert_eq!(parse_precedence(char_leaves("7"), &prec).unwrap().content(), "7");
+
+        let mut prec = PrecedenceTable::new();
+        prec.add("-", u32::MAX, Associativity::Left);
+        prec.add("^", u32::MAX, Associativity::Right);
+        assert!(parse_precedence(char_leaves("1 - 2"), &prec).is_none());
+        assert_eq!(parse_precedence(char_leaves("2 ^ 3 ^ 4"), &prec).unwrap().content(), "2 ^ 3 ^ 4");
+    }
+
+    #[test]
+    fn owned_outlives_body() {
+        let body = String::from("34 + 35");
+        let owned = add_expr_tree(&body).to_owned();
+        drop(body);
+
+        assert_eq!(owned.content(), "34 + 35");
+        assert!(owned.has_tag("addExpr"));
+        let children = owned.children().unwrap();
+        assert_eq!(children.len(), 3);
+        assert!(children[1].is_leaf());
+        assert_eq!(children[1].content(), "+");
+        assert_eq!(children[1].tags, vec!["oper", "plus"]);
+    }
+
+    #[test]
+    fn build_add_expr() {
+        let body = "34 + 35";
+        let built = ParseTokenBuilder::new()
+            .branch(vec!["expr", "addExpr"])
+                .leaf(Token{body, indices: 0..2, tags: vec!["int"]})
+                .leaf(Token{body, indices: 3..4, tags: vec!["oper", "plus"]})
+                .leaf(Token{body, indices: 5..7, tags: vec!["int"]})
+            .end()
+            .build()
+            .unwrap();
+        assert_eq!(built, add_expr_tree(body));
+
+        let nested = ParseTokenBuilder::new()
+            .branch(vec!["stmt"])
+                .child(add_expr_tree(body))
+                .branch(vec!["expr"])
+                    .leaf(Token{body, indices: 5..7, tags: vec!["int"]})
+            .build()
+            .unwrap();
+        assert_eq!(nested.depth(), 3);
+        assert_eq!(nested.leaf_count(), 4);
+    }
+
+    #[test]
+    fn build_invalid_trees() {
+        let leaf = Token{body: "34", indices: 0..2, tags: vec!["int"]};
+
+        assert!(ParseTokenBuilder::new().build().is_none());
+        assert!(ParseTokenBuilder::new().branch(vec!["expr"]).build().is_none());
+        assert!(ParseTokenBuilder::new().leaf(leaf.clone()).end().build().is_none());
+        assert!(ParseTokenBuilder::new().leaf(leaf.clone()).leaf(leaf.clone()).build().is_none());
+        assert!(ParseTokenBuilder::new().leaf(leaf).build().unwrap().is_leaf());
+    }
+
+    #[test]
+    fn custom_indentation() {
+        let pt = ParseTokenBuilder::new()
+            .branch(vec!["stmt"])
+                .child(add_expr_tree("34 + 35"))
+            .build()
+            .unwrap();
+
+        let tabbed = pt.to_string();
+        let mut spaced = String::new();
+        pt.write_with(&mut spaced, "  ").unwrap();
+
+        assert!(tabbed.contains("\t\t34"));
+        assert_eq!(spaced, tabbed.replace('\t', "  "));
+        assert!(spaced.contains("\n    34 "));
+    }
+
+    #[test]
+    fn compact_display() {
+        assert_eq!(add_expr_tree("34 + 35").to_compact(), "expr[int:34 oper:+ int:35]");
+
+        let pt = parse_tree!(["expr"] { leaf("\n(define", ["word"]), [] { leaf("\t", []) } });
+        assert_eq!(pt.to_compact(), "expr[word:\\n(define [:\\t]]");
+        assert!(!pt.to_compact().contains('\n'));
+    }
+
+    #[test]
+    fn display_has_no_trailing_separator() {
+        let pt = add_expr_tree("34 + 35");
+        let leaf = &pt.children().unwrap()[0];
+
+        assert_eq!(leaf.to_string(), "34 (\"int\")\n");
+        assert_eq!(pt.to_string(), concat!(
+            "(\"expr\"; \"addExpr\"): \"34 + 35\"\n",
+            "\t34 (\"int\")\n",
+            "\t+ (\"oper\"; \"plus\")\n",
+            "\t35 (\"int\")\n"));
+        assert!(!pt.to_string().contains("; )"));
+    }
+
+    #[test]
+    fn display_branch_content() {
+        let pt = add_expr_tree("34 + 35");
+        assert!(pt.to_string().starts_with("(\"expr\"; \"addExpr\"): \"34 + 35\"\n"));
+
+        let options = DisplayOptions { content_width: 4, ..DisplayOptions::default() };
+        let mut short = String::new();
+        pt.write_with_options(&mut short, &options).unwrap();
+        assert!(short.starts_with("(\"expr\"; \"addExpr\"): \"34 +…\"\n"));
+    }
+
+    #[test]
+    fn colored_display() {
+        let pt = add_expr_tree("34 + 35");
+
+        assert!(!pt.to_string().contains('\x1b'));
+        if cfg!(feature = "colored") {
+            let colored = pt.to_colored_string();
+            assert!(colored.contains("\x1b[36m(\"int\")\x1b[0m"));
+            assert!(colored.contains("\x1b[33m34\x1b[0m"));
+        } else {
+            assert_eq!(pt.to_colored_string(), pt.to_string());
+        }
+    }
+
+    #[test]
+    fn highlight_spans() {
+        let body = "34 + 35";
+        let pt = add_expr_tree(body);
+
+        let plus = &pt.children().unwrap()[1];
+        assert_eq!(plus.highlight(body), "34 + 35\n   ^\n");
+        assert_eq!(pt.highlight(body), "34 + 35\n^^^^^^^\n");
+
+        let body = "let x =\n\t34 +\n35;";
+        let pt = ParseToken::new_branch_from_first(vec![
+            ParseToken::new_leaf(Token{body, indices: 9..11, tags: vec!["int"]}),
+            ParseToken::new_leaf(Token{body, indices: 14..16, tags: vec!["int"]})
+        ], vec!["expr"]);
+        assert_eq!(pt.highlight(body), "\t34 +\n\t^^^^\n35;\n^^\n");
+    }
+
+    #[test]
+    fn highlight_line_breaks() {
+        let newline = |body, indices| ParseToken::new_leaf(Token{body, indices, tags: vec!["ws"]});
+        assert_eq!(newline("a\nb", 1..2).highlight("a\nb"), "a\n ^\n");
+        assert_eq!(newline("a\r\nb", 1..3).highlight("a\r\nb"), "a\n ^\n");
+        assert_eq!(newline("a\r\nb", 2..3).highlight("a\r\nb"), "a\n ^\n");
+        assert_eq!(newline("\n\n", 1..2).highlight("\n\n"), "\n^\n");
+    }
+
+    #[test]
+    fn collapse_redundant_branches() {
+        let mut pt = parse_tree!(["expr"] { ["expr", "paren"] { ["group"] { leaf("34", ["int"]) } } });
+        pt.collapse_single_child();
+
+        assert_eq!(pt, parse_tree!(["expr", "paren", "group"] { leaf("34", ["int"]) }));
+
+        let mut wide = parse_tree!(["stmt"] { ["expr"] { ["expr"] { leaf("34", ["int"]), leaf(";", []) } } });
+        wide.collapse_single_child();
+        assert_eq!(wide.tags, vec!["stmt", "expr"]);
+        assert_eq!(wide.children().unwrap().len(), 2);
+
+        let mut both = add_expr_tree("34 + 35");
+        let expected = both.clone();
+        both.collapse_single_child();
+        assert_eq!(both, expected);
+    }
+
+    #[test]
+    fn flatten_tree_to_tokens() {
+        let body = "34 + 35";
+        let pt = add_expr_tree(body);
+
+        assert_eq!(pt.flatten_to_tokens(), vec![
+            Token{body, indices: 0..2, tags: vec!["int"]},
+            Token{body, indices: 3..4, tags: vec!["oper", "plus"]},
+            Token{body, indices: 5..7, tags: vec!["int"]}
+        ]);
+
+        let pt = ParseTokenBuilder::new().branch(vec!["stmt", "expr"]).child(pt).build().unwrap();
+        let inheriting = pt.flatten_to_tokens_inheriting();
+        assert_eq!(inheriting[0].tags, vec!["int", "addExpr", "expr", "stmt"]);
+        assert_eq!(inheriting[1].indices, 3..4);
+    }
+
+    #[test]
+    fn map_tree_leaves() {
+        let upper = |t: &str| match t {
+            "int" => "INT",
+            "oper" => "OPER",
+            "plus" => "PLUS",
+            _ => "?"
+        };
+        let pt = add_expr_tree("34 + 35").map_leaves(|leaf| {
+            let mut leaf = leaf.clone();
+            leaf.tags = leaf.tags.iter().map(|t| upper(t).into()).collect();
+            leaf
+        });
+
+        assert_eq!(pt.tags, vec!["expr", "addExpr"]);
+        let children = pt.children().unwrap();
+        assert_eq!(children[0].tags, vec!["INT"]);
+        assert_eq!(children[1].tags, vec!["OPER", "PLUS"]);
+        assert_eq!(pt.content(), "34 + 35");
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        let pt = add_expr_tree("34 + 35");
+
+        let value: i64 = pt.fold(
+            |leaf| if leaf.has_tag("int") { leaf.content().parse().unwrap() } else { 0 },
+            |branch, values| if branch.has_tag("addExpr") { values[0] + values[2] } else { values[0] });
+        assert_eq!(value, 69);
+
+        let count = pt.fold(|_| 1, |_, values: Vec<usize>| 1 + values.iter().sum::<usize>());
+        assert_eq!(count, pt.node_count());
+    }
+
+    struct LeafText(String);
+
+    impl<'a> Visitor<'a> for LeafText {
+        fn visit_leaf(&mut self, leaf: &ParseToken<'a>) {
+            self.0.push_str(leaf.content());
+        }
+    }
+
+    struct BranchCounter(usize);
+
+    impl<'a> Visitor<'a> for BranchCounter {
+        fn visit_branch(&mut self, branch: &ParseToken<'a>) {
+            self.0 += 1;
+            walk_children(self, branch);
+        }
+    }
+
+    #[test]
+    fn visit_tree() {
+        let body = "34 + 35";
+        let pt = ParseTokenBuilder::new().branch(vec!["stmt"]).child(add_expr_tree(body)).build().unwrap();
+
+        let mut text = LeafText(String::new());
+        pt.accept(&mut text);
+        assert_eq!(text.0, "34+35");
+
+        let mut branches = BranchCounter(0);
+        pt.accept(&mut branches);
+        assert_eq!(branches.0, 2);
+    }
+
+    #[test]
+    fn zipper_edit() {
+        let body = "34 + 35";
+        let mut z = ParseZipper::new(add_expr_tree(body));
+
+        assert!(!z.up());
+        assert!(!z.left());
+        assert!(z.down(0));
+        assert!(!z.down(0));
+        assert!(!z.left());
+        assert!(z.right());
+        assert_eq!(z.node().content(), "+");
+
+        let minus = ParseToken::new_leaf(Token{body: "34 - 35", indices: 3..4, tags: vec!["oper", "plus"]});
+        let old = z.replace(minus);
+        assert_eq!(old.content(), "+");
+
+        assert!(z.right());
+        assert!(!z.right());
+        assert!(z.left());
+        assert!(z.left());
+        assert_eq!(z.node().content(), "34");
+
+        let tree = z.into_tree();
+        assert_eq!(tree, add_expr_tree("34 - 35"));
+        assert_eq!(tree.to_source(), "34-35");
+    }
+
+    #[test]
+    fn replace_expr_subtrees() {
+        let body = "34 + 35;";
+        let summarize = |pt: &ParseToken<'static>| ParseToken {
+            node: ParseNode::Leaf(pt.content_range().unwrap()),
+            body,
+            tags: vec!["summary".into()]
+        };
+
+        let mut pt = ParseTokenBuilder::new()
+            .branch(vec!["stmt"])
+                .child(add_expr_tree(body))
+                .leaf(Token{body, indices: 7..8, tags: vec!["semi"]})
+            .build()
+            .unwrap();
+        pt.replace_subtree_where(|t| t.has_tag("expr"), summarize);
+        assert_eq!(pt.to_compact(), "stmt[summary:34 + 35 semi:;]");
+
+        let mut nested = ParseTokenBuilder::new()
+            .branch(vec!["expr"])
+                .child(add_expr_tree(body))
+            .build()
+            .unwrap();
+        nested.replace_subtree_where(|t| t.has_tag("expr"), summarize);
+        assert_eq!(nested.to_compact(), "summary:34 + 35");
+    }
+
+    #[test]
+    fn insert_and_remove_children() {
+        let body = "34 + 35 + 36";
+        let mut pt = add_expr_tree(body);
+
+        pt.insert_child(3, ParseToken::new_leaf(Token{body, indices: 8..9, tags: vec!["oper", "plus"]})).unwrap();
+        pt.insert_child(4, ParseToken::new_leaf(Token{body, indices: 10..12, tags: vec!["int"]})).unwrap();
+        assert_eq!(pt.content(), "34 + 35 + 36");
+        assert_eq!(pt.insert_child(9, empty_parse_token()), Err(EditError::OutOfRange { index: 9, len: 5 }));
+
+        let first = pt.remove_child(0).unwrap();
+        assert_eq!(first.content(), "34");
+        assert_eq!(pt.content(), "+ 35 + 36");
+        assert!(pt.remove_child(4).is_none());
+
+        let mut leaf = first;
+        assert_eq!(leaf.insert_child(0, empty_parse_token()), Err(EditError::NotABranch));
+        assert!(leaf.remove_child(0).is_none());
+        assert_eq!(EditError::NotABranch.to_string(), "cannot edit the children of a leaf");
+    }
+
+    #[test]
+    fn select_by_selector() {
+        let body = "34 + 35";
+        let pt = add_expr_tree(body);
+        let ints = pt.select("expr > int");
+        assert_eq!(ints.len(), 2);
+        assert!(ints.iter().all(|t| t.has_tag("int")));
+
+        let nested = ParseTokenBuilder::new()
+            .branch(vec!["expr"])
+                .leaf(Token{body, indices: 0..2, tags: vec!["int"]})
+                .branch(vec!["group"])
+                    .leaf(Token{body, indices: 5..7, tags: vec!["int"]})
+            .build()
+            .unwrap();
+        let direct: Vec<&str> = nested.select("expr>int").iter().map(|t| t.content()).collect();
+        assert_eq!(direct, vec!["34"]);
+        let anywhere: Vec<&str> = nested.select("expr int").iter().map(|t| t.content()).collect();
+        assert_eq!(anywhere, vec!["34", "35"]);
+        assert_eq!(nested.select("group > *").len(), 1);
+        assert_eq!(nested.select("*").len(), 4);
+        assert!(nested.select("> int").is_empty());
+        assert!(nested.select("expr >").is_empty());
+        assert!(nested.select("").is_empty());
+    }
+
+    #[test]
+    fn child_helpers() {
+        let pt = add_expr_tree("34 + 35");
+
+        assert!(pt.nth_child(1).unwrap().has_tag("plus"));
+        assert_eq!(pt.first_child().unwrap().content(), "34");
+        assert_eq!(pt.last_child().unwrap().content(), "35");
+        assert!(pt.nth_child(3).is_none());
+        assert!(pt.nth_child(0).unwrap().first_child().is_none());
+        assert!(ParseToken::new_branch(vec![], "", vec![]).last_child().is_none());
+    }
+
+    #[test]
+    fn sibling_operands() {
+        let pt = add_expr_tree("34 + 35");
+        let index = TreeIndex::new(&pt);
+
+        let plus = index.id_of(pt.find_first_by_tag("plus").unwrap()).unwrap();
+        let operands: Vec<&str> = index.siblings_with_tag(plus, "int").iter()
+            .map(|&id| index.node(id).unwrap().content())
+            .collect();
+        assert_eq!(operands, vec!["34", "35"]);
+        assert_eq!(index.siblings(plus).len(), 2);
+        assert_eq!(index.children(0), &[1, 2, 3]);
+        assert!(index.siblings(0).is_empty());
+    }
+
+    #[test]
+    fn parse_tokens_without_sentinel() {
+        let tokens = str_to_tokens("34 + 35");
+        let pts = parse_tokens_from(&tokens);
+
+        assert_eq!(pts.len(), tokens.len());
+        assert_eq!(tokens_to_parse_tokens(tokens.clone()).len(), tokens.len() + 1);
+        for (pt, tok) in pts.iter().zip(&tokens) {
+            assert_eq!(pt.content_range(), Some(tok.indices.clone()));
+            assert_eq!(pt.tags, tok.tags);
+        }
+    }
+
+    #[test]
+    fn optional_sentinel() {
+        let tokens = vec![token_from_string("34", vec!["int"])];
+
+        let with = tokens_to_parse_tokens_maybe_sentinel(tokens.clone(), true);
+        let without = tokens_to_parse_tokens_maybe_sentinel(tokens.clone(), false);
+        assert_eq!(with.len(), 2);
+        assert_eq!(with[1], empty_parse_token());
+        assert_eq!(without[..], with[..1]);
+        assert_eq!(tokens_to_parse_tokens(tokens), with);
+    }
+
+    #[test]
+    fn arena_round_trip() {
+        let body = "34 + 35";
+        let tree = add_expr_tree(body);
+
+        let mut arena = ParseArena::new();
+        let root = arena.add_tree(&tree);
+        assert_eq!(arena.len(), 4);
+        assert_eq!(arena.content(root), "34 + 35");
+        assert_eq!(arena.to_tree(root), tree);
+
+        // Regroup the operator and right operand without cloning any subtree.
+        let children = arena.children_mut(root).unwrap().split_off(1);
+        let rhs = arena.new_branch_from_first(children, vec!["rhs"]).unwrap();
+        arena.children_mut(root).unwrap().push(rhs);
+        assert!(arena.has_tag(rhs, "rhs"));
+        assert_eq!(arena.content(rhs), "+ 35");
+        assert_eq!(arena.to_tree(root).to_sexpr(), "(expr 34 (rhs + 35))");
+        assert_eq!(arena.new_branch_from_first(vec![], vec![]), None);
+    }
+
+    #[test]
+    fn content_range_of_deep_chain() {
+        let body = "(x)";
+        let mut chain = ParseToken::new_leaf(Token{body, indices: 1..2, tags: vec!["x"]});
+        for _ in 0..100_000 {
+            chain = ParseToken::new_branch(vec![chain], body, vec!["expr"]);
+        }
+        assert_eq!(chain.content_range(), Some(1..2));
+        assert_eq!(chain.content(), "x");
+
+        // Dropping the chain normally would recurse once per level, so take
+        // it apart one level at a time
+        let mut next = Some(chain);
+        while let Some(mut pt) = next {
+            next = pt.children_mut().and_then(|c| c.pop());
+        }
+    }
+
+    #[test]
+    fn owned_tags_from_map_tags() {
+        let body = "34 + 35";
+        let mut tree = add_expr_tree(body);
+        tree.map_tags(|t| if t == "expr" { format!("prefixed:{}", t).into() } else { t });
+
+        assert!(tree.has_tag("prefixed:expr"));
+        assert!(!tree.has_tag("expr"));
+        assert!(matches!(tree.tags[0], std::borrow::Cow::Owned(_)));
+        assert!(matches!(tree.tags[1], std::borrow::Cow::Borrowed("addExpr")));
+        assert_eq!(tree.tag_strs(), vec!["prefixed:expr", "addExpr"]);
+
+        tree.add_tag(String::from("checked"));
+        assert!(tree.has_tag("checked"));
+        assert_eq!(tree.to_sexpr(), "(prefixed:expr 34 + 35)");
+    }
+
+    #[test]
+    fn sort_by_position() {
+        let text = "a b c d";
+        let mut pts = char_leaves(text);
+        pts.push(ParseToken::new_branch(vec![], text, vec!["empty"]));
+        pts.swap(0, 4);
+        pts.swap(1, 3);
+        pts.sort_by(ParseToken::cmp_position);
+        let order: Vec<&str> = pts.iter().map(|pt| pt.content()).collect();
+        assert_eq!(order, vec!["a", "b", "c", "d", ""]);
+
+        let tree = add_expr_tree("34 + 35");
+        let mut found: Vec<&ParseToken> = tree.find_all_by_tag("int");
+        found.reverse();
+        found.sort_by(|a, b| a.cmp_position(b));
+        assert_eq!(found[0].content(), "34");
+        assert!(tree.cmp_position(found[1]).is_lt());
+    }
+
+    #[test]
+    fn xml_document() {
+        let pt = add_expr_tree("34 + 35");
+        let xml = pt.to_xml();
+        assert_eq!(xml, "<node tags=\"expr addExpr\"><leaf tags=\"int\">34</leaf>\
+            <leaf tags=\"oper plus\">+</leaf><leaf tags=\"int\">35</leaf></node>");
+
+        // Every element that is opened is closed, innermost first
+        let mut open = vec![];
+        for part in xml.split('<').skip(1) {
+            let name = part.split([' ', '>']).next().unwrap();
+            match name.strip_prefix('/') {
+                Some(closing) => assert_eq!(open.pop(), Some(closing)),
+                None => open.push(name)
+            }
+        }
+        assert!(open.is_empty());
+
+        let pt = parse_tree!(["str"] { leaf("\"red\" <&>", ["word"]) });
+        assert_eq!(pt.to_xml(), "<node tags=\"str\"><leaf tags=\"word\">&quot;red&quot; &lt;&amp;&gt;</leaf></node>");
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let body = "34 \"a\\b\tc\" é ☃";
+        let leaf = |indices, tags| ParseToken::new_leaf(Token { body, indices, tags });
+        let pt = ParseToken::new_branch_from_first(vec![
+            leaf(0..2, vec!["int"]),
+            ParseToken::new_branch_from_first(vec![leaf(3..10, vec!["word"])], vec!["str"]),
+            leaf(11..17, vec![])
+        ], vec!["expr", "addExpr"]);
+        assert_eq!(pt.nth_child(2).unwrap().content(), "é ☃");
+        assert_eq!(from_json(&pt.to_json()), Ok(pt.to_owned()));
+        assert_eq!(from_json(&add_expr_tree("34 + 35").to_json()), Ok(add_expr_tree("34 + 35").to_owned()));
+
+        let leaf = from_json(r#" {"text": "\u0041\ud83d\ude00", "extra": [1, true, null], "tags": []} "#).unwrap();
+        assert_eq!(leaf.content(), "A😀");
+    }
+
+    #[test]
+    fn json_errors() {
+        let err = from_json(r#"{"tags": [3], "text": "x"}"#).unwrap_err();
+        assert_eq!(err.message, "expected a tag to be a string, found a number");
+        assert_eq!(err.span, 10..11);
+
+        let err = from_json(r#"{"text": "x"}"#).unwrap_err();
+        assert_eq!(err.to_string(), "missing field \"tags\" at 0..13");
+
+        let err = from_json(r#"{"tags": [], "children": []}"#).unwrap_err();
+        assert_eq!(err.message, "missing field \"content\" of a branch");
+
+        let err = from_json(r#"{"tags": []}"#).unwrap_err();
+        assert_eq!(err.message, "missing field \"text\" or \"children\"");
+
+        let err = from_json(r#"{"tags": [], "text": "x"#).unwrap_err();
+        assert_eq!(err.message, "unterminated string");
+
+        let err = from_json(r#"{"tags": [] "text": "x"}"#).unwrap_err();
+        assert_eq!(err.span, 12..13);
+
+        assert!(from_json(r#"{"tags": [], "text": "x"} {}"#).is_err());
+
+        let err = from_json(&"[".repeat(200_000)).unwrap_err();
+        assert_eq!(err.message, "nested more than 2048 levels deep");
+        assert_eq!(err.span, 2048..2049);
+        let deepest = "[".repeat(MAX_JSON_DEPTH) + &"]".repeat(MAX_JSON_DEPTH);
+        assert_eq!(from_json(&deepest).unwrap_err().message, "expected a token object, found an array");
+        let branch = r#"{"tags": [], "content": "x", "children": ["#;
+        let chain = branch.repeat(1023) + r#"{"tags": [], "text": "x"}"# + &"]}".repeat(1023);
+        let chain = from_json(&chain).unwrap();
+        let (mut pt, mut levels) = (&chain, 1);
+        while let Some([child]) = pt.children() {
+            pt = child;
+            levels += 1;
+        }
+        assert_eq!(levels, 1024);
+        assert!(pt.is_leaf());
+
+        let err = from_json(r#"{"tags": [], "text": "\ud83d\u0041"}"#).unwrap_err();
+        assert_eq!(err.message, "invalid unicode escape");
+        assert_eq!(err.span, 22..34);
+        let snowman = from_json(r#"{"tags": [], "text": "\ud83d\ude00"}"#).unwrap();
+        assert_eq!(snowman.content(), "\u{1F600}");
+
+        for bad in ["-+e", "-", "1.", "1e", "1e+", "-.5"] {
+            let err = from_json(&format!(r#"{{"tags": [{}], "text": "x"}}"#, bad)).unwrap_err();
+            assert_eq!(err.message, "invalid number", "{}", bad);
+        }
+        let err = from_json(r#"{"tags": [-1.5e+3], "text": "x"}"#).unwrap_err();
+        assert_eq!(err.message, "expected a tag to be a string, found a number");
+    }
+
+    #[test]
+    fn join_trees() {
+        let body = "34 + 35";
+        let program = ParseToken::join(vec![add_expr_tree(body), add_expr_tree(body)], vec!["program"]).unwrap();
+        assert!(program.has_tag("program"));
+        assert_eq!(program.children().unwrap().len(), 2);
+        assert_eq!(program.first_child(), program.last_child());
+        assert_eq!(program.to_sexpr(), "(program (expr 34 + 35) (expr 34 + 35))");
+
+        let other = String::from(body);
+        assert!(ParseToken::join(vec![add_expr_tree(body), add_expr_tree(&other)], vec!["program"]).is_none());
+        assert!(ParseToken::join(vec![], vec!["program"]).is_none());
+    }
+
+    #[test]
+    fn split_on_commas() {
+        let tag_commas = |pts: Vec<ParseToken<'static>>| pts.into_iter().map(|mut pt| {
+            if pt.content() == "," {
+                pt.tags = vec!["comma".into()];
+            }
+            pt
+        }).collect::<Vec<_>>();
+
+        let list = ParseToken::new_branch_from_first(tag_commas(char_leaves("a , b , c")), vec!["list"]);
+        let groups = list.split_children_on("comma");
+        let contents: Vec<Vec<&str>> = groups.iter()
+            .map(|g| g.iter().map(|pt| pt.content()).collect())
+            .collect();
+        assert_eq!(contents, vec![vec!["a"], vec!["b"], vec!["c"]]);
+
+        let trailing = ParseToken::new_branch_from_first(tag_commas(char_leaves("a , , b ,")), vec!["list"]);
+        let sizes: Vec<usize> = trailing.split_children_on("comma").iter().map(|g| g.len()).collect();
+        assert_eq!(sizes, vec![1, 0, 1, 0]);
+        assert!(list.first_child().unwrap().split_children_on("comma").is_empty());
+    }
+
+    #[test]
+    fn subtree_tag_predicates() {
+        let pt = add_expr_tree("34 + 35");
+        assert!(pt.contains_tag_anywhere("oper"));
+        assert!(pt.contains_tag_anywhere("addExpr"));
+        assert!(!pt.contains_tag_anywhere("string"));
+        assert!(!pt.all_leaves_have_tag("int"));
+        assert!(pt.first_child().unwrap().all_leaves_have_tag("int"));
+        assert!(ParseToken::new_branch(vec![], "", vec![]).all_leaves_have_tag("int"));
+    }
+
+    #[test]
+    fn non_terminating_rules() {
+        let text = "ab";
+        let swap = |pts: Vec<ParseToken<'static>>| {
+            let mut pt = pts[0].clone();
+            pt.tags = match pt.first_tag() {
+                Some("a") => vec!["b".into()],
+                _ => vec!["a".into()]
+            };
+            Some(vec![pt])
+        };
+        let mut pts = char_leaves(text);
+        let err = try_process_parse_rules(vec![swap], &mut pts, 1000).unwrap_err();
+        assert_eq!(err, RuleError::NonTerminating { rule_index: 0, iterations: 4 });
+
+        let noop = |pts: Vec<ParseToken<'static>>| Some(pts);
+        let grow = |pts: Vec<ParseToken<'static>>| Some(vec![pts[0].clone(), pts[0].clone()]);
+        let mut pts = char_leaves(text);
+        let err = try_process_parse_rules(vec![noop, grow], &mut pts, 50).unwrap_err();
+        assert_eq!(err, RuleError::NonTerminating { rule_index: 1, iterations: 51 });
+        assert_eq!(err.to_string(), "rule 1 did not terminate after 51 replacements");
+
+        let mut pts = char_leaves(text);
+        assert_eq!(try_process_parse_rules(vec![noop], &mut pts, 0), Ok(()));
+    }
+
+    #[test]
+    fn rules_that_keep_content() {
+        // both replacements have the same tags and content as what they replace
+        let text = "a+a";
+        let other = "a";
+        let move_first = |pts: Vec<ParseToken<'static>>| {
+            let mut pt = pts[0].clone();
+            if pt.content_range() == Some(0..1) {
+                pt.node = ParseNode::Leaf(2..3);
+            }
+            Some(vec![pt])
+        };
+        let mut pts = char_leaves(text);
+        assert_eq!(try_process_parse_rules(vec![move_first], &mut pts, 10), Ok(()));
+        assert_eq!(pts[0].content_range(), Some(2..3));
+
+        let reborrow = |pts: Vec<ParseToken<'static>>| {
+            let mut pt = pts[0].clone();
+            if pt.content() == "a" {
+                pt.body = other;
+                pt.node = ParseNode::Leaf(0..1);
+            }
+            Some(vec![pt])
+        };
+        let mut pts = char_leaves(text);
+        assert_eq!(try_process_parse_rules(vec![reborrow], &mut pts, 10), Ok(()));
+        assert!(std::ptr::eq(pts[0].body, other) && std::ptr::eq(pts[2].body, other));
+        assert!(std::ptr::eq(pts[1].body, text));
+    }
+
+    #[test]
+    fn merge_word_characters() {
+        let text = "abc+de";
+        let mut leaves = tokens_to_parse_tokens_maybe_sentinel(str_to_tokens(text), false);
+        leaves.pop();
+        for leaf in leaves.iter_mut() {
+            let tag = if leaf.content() == "+" { "oper" } else { "word" };
+            leaf.tags = vec![tag.into()];
+        }
+        let mut pt = ParseToken::new_branch_from_first(leaves, vec!["expr"]);
+        pt.merge_adjacent_leaves(|a, b| a.has_tag("word") && b.has_tag("word"));
+
+        let children = pt.children().unwrap();
+        let contents: Vec<&str> = children.iter().map(|c| c.content()).collect();
+        assert_eq!(contents, vec!["abc", "+", "de"]);
+        assert!(children[0].is_leaf());
+        assert_eq!(children[0].tags, vec!["word"]);
+        assert_eq!(children[0].content_range(), Some(0..3));
+    }
+
+    #[test]
+    fn leaf_as_token() {
+        let token = Token { body: "34 + 35", indices: 3..4, tags: vec!["oper", "plus"] };
+        let leaf = ParseToken::new_leaf(token.clone());
+        assert_eq!(leaf.as_token(), Some(token));
+        assert_eq!(add_expr_tree("34 + 35").as_token(), None);
+    }
+
+    #[test]
+    fn group_digit_runs() {
+        let mut pts = char_leaves("12 + 345");
+        for pt in pts.iter_mut() {
+            if pt.content().chars().all(|c| c.is_ascii_digit()) {
+                pt.tags = vec!["digit".into()];
+            }
+        }
+        let grouped = group_runs(pts, "digit", vec!["int"]);
+        let shape: Vec<String> = grouped.iter().map(|pt| pt.to_sexpr()).collect();
+        assert_eq!(shape, vec!["(int 1 2)", "+", "(int 3 4 5)"]);
+        assert_eq!(grouped[2].content(), "345");
+    }
+
+    #[test]
+    fn compare_structure_only() {
+        let pt = add_expr_tree("34 + 35");
+        let mut retagged = add_expr_tree("34 + 35");
+        retagged.map_tags(|t| if t == "int" { "number".into() } else { t });
+        assert_ne!(pt, retagged);
+        assert!(pt.structural_eq(&retagged));
+
+        let other = add_expr_tree("34 - 35");
+        assert!(!pt.structural_eq(&other));
+        assert!(!pt.structural_eq(pt.first_child().unwrap()));
+    }
+
+    #[test]
+    fn inner_range_skips_whitespace() {
+        let body = "  34 ";
+        let pt = ParseToken::new_branch_from_first(vec![
+            ParseToken::new_leaf(Token { body, indices: 0..2, tags: vec!["ws"] }),
+            ParseToken::new_leaf(Token { body, indices: 2..4, tags: vec!["int"] }),
+            ParseToken::new_leaf(Token { body, indices: 4..5, tags: vec!["ws"] })
+        ], vec!["expr"]);
+        assert_eq!(pt.content_range(), Some(0..5));
+        assert_eq!(pt.inner_content_range(), Some(2..4));
+
+        let blank = ParseToken::new_branch_from_first(vec![pt.first_child().unwrap().clone()], vec![]);
+        assert_eq!(blank.inner_content_range(), None);
+    }
+
+    #[test]
+    fn leaves_from_tokens() {
+        let token = Token { body: "34 + 35", indices: 0..2, tags: vec!["int"] };
+        let leaf: ParseToken = token.clone().into();
+        assert_eq!(leaf, ParseToken::new_leaf(token.clone()));
+        assert_eq!(leaf.content_range(), Some(0..2));
+
+        let tokens = str_to_tokens("ab");
+        let leaves: Vec<ParseToken> = tokens.clone().into_iter().map(ParseToken::from).collect();
+        assert_eq!(leaves, parse_tokens_from(&tokens));
+    }
+
+    #[test]
+    fn retain_non_whitespace_children() {
+        let body = "( 34 )";
+        let leaf = |indices, tags| ParseToken::new_leaf(Token { body, indices, tags });
+        let inner = ParseToken::new_branch_from_first(vec![leaf(2..4, vec!["int"]), leaf(4..5, vec!["ws"])], vec!["num"]);
+        let mut pt = ParseToken::new_branch_from_first(vec![
+            leaf(0..1, vec!["("]),
+            leaf(1..2, vec!["ws"]),
+            inner,
+            leaf(5..6, vec![")"])
+        ], vec!["expr"]);
+        pt.retain_children(|c| !c.has_tag("ws"));
+
+        assert_eq!(pt.to_sexpr(), "(expr ( (num 34  ) ))");
+        let mut leaf = leaf(0..1, vec!["ws"]);
+        leaf.retain_children(|_| false);
+        assert!(leaf.is_leaf());
+    }
+
+    #[test]
+    fn numeric_leaves() {
+        let pt = add_expr_tree("34 + 35");
+        let children = pt.children().unwrap();
+        assert_eq!(children[0].as_i64(), Some(34));
+        assert_eq!(children[1].as_i64(), None);
+        assert_eq!(children[2].as_f64(), Some(35.0));
+        assert_eq!(pt.as_i64(), None);
+
+        let leaf = ParseToken::new_leaf(Token { body: "-2.5", indices: 0..4, tags: vec!["float"] });
+        assert_eq!(leaf.as_f64(), Some(-2.5));
+        assert_eq!(leaf.as_i64(), None);
+    }
+
+    #[test]
+    fn dedup_repeated_tags() {
+        let mut pt = add_expr_tree("34 + 35");
+        pt.tags.insert(1, "expr".into());
+        pt.children_mut().unwrap()[1].tags.push("oper".into());
+        assert_eq!(pt.tags, vec!["expr", "expr", "addExpr"]);
+
+        pt.dedup_tags();
+        assert_eq!(pt.tags, vec!["expr", "addExpr"]);
+        assert_eq!(pt.children().unwrap()[1].tags, vec!["oper", "plus"]);
+    }
+
+    #[test]
+    fn rename_tags_throughout() {
+        let mut pt = add_expr_tree("34 + 35");
+        pt.rename_tag("int", "number");
+        assert!(pt.find_all_by_tag("int").is_empty());
+        let numbers: Vec<&str> = pt.find_all_by_tag("number").iter().map(|n| n.content()).collect();
+        assert_eq!(numbers, vec!["34", "35"]);
+
+        pt.rename_tag("expr", "addExpr");
+        assert_eq!(pt.tags, vec!["addExpr"]);
+        pt.rename_tag("plus", String::from("sum"));
+        assert_eq!(pt.children().unwrap()[1].tags, vec!["oper", "sum"]);
+    }
+
+    #[test]
+    fn diff_operator_change() {
+        let plus = add_expr_tree("34 + 35");
+        let minus_text = String::from("34 - 35");
+        let minus = add_expr_tree(&minus_text);
+        assert_eq!(plus.diff(&minus), vec![
+            TreeDiff::TextChanged { path: vec![1], before: "+".to_string(), after: "-".to_string() }
+        ]);
+        assert!(plus.diff(&plus).is_empty());
+
+        let mut shorter = add_expr_tree("34 + 35");
+        shorter.remove_child(2);
+        shorter.add_tag("short");
+        assert_eq!(plus.diff(&shorter), vec![
+            TreeDiff::TagsChanged {
+                path: vec![],
+                before: vec!["expr".to_string(), "addExpr".to_string()],
+                after: vec!["expr".to_string(), "addExpr".to_string(), "short".to_string()]
+            },
+            TreeDiff::ChildRemoved { path: vec![2] }
+        ]);
+        assert_eq!(shorter.diff(&plus).last(), Some(&TreeDiff::ChildAdded { path: vec![2] }));
+        assert_eq!(plus.diff(plus.first_child().unwrap()).last(), Some(&TreeDiff::KindChanged { path: vec![] }));
+    }
+
+    #[test]
+    fn content_length() {
+        let pt = add_expr_tree("34 + 35");
+        assert_eq!(pt.content_len(), 7);
+        assert_eq!(pt.content_bytes(), b"34 + 35");
+        assert_eq!(pt.last_child().unwrap().content_len(), 2);
+        assert_eq!(ParseToken::new_branch(vec![], "", vec![]).content_len(), 0);
+    }
+
+    #[test]
+    fn bad_ranges_in_multibyte_text() {
+        let body = "café";
+        let split = ParseToken::new_leaf(Token { body, indices: 3..4, tags: vec!["word"] });
+        let past_end = ParseToken::new_leaf(Token { body, indices: 2..9, tags: vec!["word"] });
+        let whole = ParseToken::new_leaf(Token { body, indices: 0..5, tags: vec!["word"] });
+        assert_eq!(split.content(), "");
+        assert_eq!(past_end.content(), "");
+        assert_eq!(whole.content(), "café");
+        assert_eq!(split.content_len(), 0);
+        assert_eq!(past_end.content_len(), past_end.content_bytes().len());
+        assert_eq!(past_end.content_len(), 0);
+        assert_eq!(whole.content_len(), 5);
+
+        let pt = ParseToken::new_branch_from_first(vec![split.clone(), whole], vec!["expr"]);
+        assert_eq!(pt.content(), "café");
+        assert_eq!(pt.to_source(), "café");
+        assert_eq!(split.to_string(), " (\"word\")\n");
+        assert_eq!(split, past_end);
+        assert!(split.to_json().contains("\"text\": \"\""));
+    }
+
+    #[test]
+    fn debug_format() {
+        let pt = add_expr_tree("34 + 35");
+        let debug = format!("{:?}", pt);
+        assert!(debug.contains("Branch"));
+        assert!(debug.contains("\"addExpr\""));
+        assert_eq!(format!("{:?}", pt.first_child().unwrap()), "Leaf { tags: [\"int\"], content: \"34\" }");
+        assert!(debug.starts_with("Branch { tags: [\"expr\", \"addExpr\"], content: \"34 + 35\", children: [Leaf {"));
+    }
+
+    #[test]
+    fn reindex_into_larger_buffer() {
+        let mut pt = add_expr_tree("34 + 35");
+        assert!(pt.reindex(10));
+        assert_eq!(pt.content_range(), Some(10..17));
+        assert_eq!(pt.first_child().unwrap().content_range(), Some(10..12));
+
+        assert!(!pt.reindex(-11));
+        assert_eq!(pt.content_range(), Some(10..17));
+        assert!(pt.reindex(-10));
+        assert_eq!(pt.content(), "34 + 35");
+    }
+
+    #[test]
+    fn wrap_operator_sequences() {
+        let leaves = add_expr_tree("34 + 35").children().unwrap().to_vec();
+        let wrapped = wrap_tag_sequence(leaves.clone(), &["int", "oper", "int"], vec!["addExpr"]);
+        assert_eq!(wrapped.len(), 1);
+        assert_eq!(wrapped[0].to_sexpr(), "(addExpr 34 + 35)");
+
+        // "1 + 2 + 3": the first match wins, and the leftover tokens are kept
+        let body = "1 + 2 + 3";
+        let leaf = |i: usize, tag| ParseToken::new_leaf(Token { body, indices: i..i + 1, tags: vec![tag] });
+        let pts = vec![leaf(0, "int"), leaf(2, "oper"), leaf(4, "int"), leaf(6, "oper"), leaf(8, "int")];
+        let wrapped = wrap_tag_sequence(pts, &["int", "oper", "int"], vec!["addExpr"]);
+        let shape: Vec<String> = wrapped.iter().map(|pt| pt.to_sexpr()).collect();
+        assert_eq!(shape, vec!["(addExpr 1 + 2)", "+", "3"]);
+
+        assert_eq!(wrap_tag_sequence(leaves.clone(), &[], vec!["addExpr"]), leaves);
+    }
+
+    #[test]
+    fn match_children_against_pattern() {
+        let pt = add_expr_tree("34 + 35");
+        assert!(pt.children_match(&["int", "*", "int"]));
+        assert!(pt.children_match(&["int", "plus", "int"]));
+        assert!(!pt.children_match(&["int", "*"]));
+        assert!(!pt.children_match(&["*", "*", "oper"]));
+        assert!(!pt.first_child().unwrap().children_match(&[]));
+    }
+
+    #[test]
+    fn address_by_path() {
+        let pt = ParseToken::new_branch_from_first(vec![add_expr_tree("34 + 35")], vec!["program"]);
+        let expr = pt.first_child().unwrap();
+        assert_eq!(expr.subtree_at_path(&[1]).unwrap().content(), "+");
+        assert_eq!(pt.subtree_at_path(&[0, 2]).unwrap().content(), "35");
+        assert!(std::ptr::eq(pt.subtree_at_path(&[]).unwrap(), &pt));
+        assert!(pt.subtree_at_path(&[0, 3]).is_none());
+        assert!(pt.subtree_at_path(&[0, 1, 0]).is_none());
+
+        let index = TreeIndex::new(&pt);
+        for id in 0..index.len() {
+            let path = index.path_of(id).unwrap();
+            assert!(std::ptr::eq(pt.subtree_at_path(&path).unwrap(), index.node(id).unwrap()));
+        }
+        assert_eq!(index.path_of(3), Some(vec![0, 1]));
+        assert_eq!(index.path_of(9), None);
+    }
+
+    #[test]
+    fn stream_json() {
+        let pt = add_expr_tree("34 + \"35\"");
+        let mut out: Vec<u8> = vec![];
+        pt.write_json(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), pt.to_json());
+
+        let mut full = [0u8; 8];
+        assert!(pt.write_json(&mut &mut full[..]).is_err());
+    }
+
+    #[test]
+    fn join_and_first_tags() {
+        let pt = add_expr_tree("34 + 35");
+        let leaf = pt.first_child().unwrap();
+        assert_eq!(leaf.tags_joined("; "), "int");
+        assert_eq!(pt.tags_joined("; "), "expr; addExpr");
+        assert_eq!(pt.first_tag(), Some("expr"));
+        assert_eq!(ParseToken::new_branch(vec![], "", vec![]).first_tag(), None);
+    }
+
+    #[test]
+    fn decompose_by_value() {
+        let body = "34 + 35";
+        let children = add_expr_tree(body).into_children().unwrap();
+        assert_eq!(children.len(), 3);
+        assert!(children.iter().all(|c| c.is_leaf()));
+        assert_eq!(children.iter().map(|c| c.content()).collect::<Vec<_>>(), ["34", "+", "35"]);
+
+        let leaf = children.into_iter().nth(1).unwrap();
+        let leaf = leaf.into_children().unwrap_err();
+        assert!(leaf.has_tag("plus"));
+    }
+
+    #[test]
+    fn keep_first_tags() {
+        let body = "34 + 35";
+        let tree = add_expr_tree(body);
+        let first_tags = ["expr", "int", "oper"];
+        let mapped = tree.map_nodes(|pt| first_tags.into_iter().filter(|t| pt.first_tag() == Some(t)).collect());
+        assert_eq!(mapped.tag_strs(), ["expr"]);
+        assert_eq!(mapped.nth_child(0).unwrap().tag_strs(), ["int"]);
+        assert_eq!(mapped.nth_child(1).unwrap().tag_strs(), ["oper"]);
+        assert!(mapped.structural_eq(&tree));
+        assert_eq!(mapped.content(), tree.content());
+        assert_eq!(tree.tag_strs(), ["expr", "addExpr"]);
+    }
+
+    fn bracket_pairs() -> Vec<(&'static str, &'static str, Vec<&'static str>)> {
+        vec![("(", ")", vec!["expr"]), ("[", "]", vec!["list"]), ("{", "}", vec!["block"])]
+    }
+
+    #[test]
+    fn match_mixed_brackets() {
+        let pts = match_bracket_pairs(char_leaves("( [ ] )"), &bracket_pairs()).unwrap();
+
+        assert_eq!(pts.len(), 1);
+        assert!(pts[0].has_tag("expr"));
+        let inner = pts[0].children().unwrap();
+        assert_eq!(inner.len(), 1);
+        assert!(inner[0].has_tag("list"));
+        assert_eq!(inner[0].children().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn mismatched_brackets_error() {
+        let err = match_bracket_pairs(char_leaves("( ]"), &bracket_pairs()).unwrap_err();
+        assert_eq!(err.message, "mismatched \"]\", expected \")\"");
+        assert_eq!(err.span, 2..3);
+
+        let err = match_bracket_pairs(char_leaves("{ ( }"), &bracket_pairs()).unwrap_err();
+        assert_eq!(err.token.as_ref().map(|pt| pt.content()), Some("}"));
+    }
+
+    #[test]
+    fn leaf_at_offset() {
+        let body = "34 + 35";
+        let tree = add_expr_tree(body);
+        assert_eq!(tree.token_at_byte(3).map(|pt| pt.content()), Some("+"));
+        assert_eq!(tree.token_at_byte(1).map(|pt| pt.content()), Some("34"));
+        assert_eq!(tree.token_at_byte(5).map(|pt| pt.content()), Some("35"));
+        assert!(tree.token_at_byte(2).is_none());
+        assert!(tree.token_at_byte(7).is_none());
+
+        // the first child spans 0..7 but only has leaves at either end
+        let leaf = |indices, tags| ParseToken::new_leaf(Token { body, indices, tags });
+        let reordered = ParseToken::new_branch(vec![
+            ParseToken::new_branch(vec![leaf(5..7, vec!["int"]), leaf(0..2, vec!["int"])], body, vec!["pair"]),
+            leaf(3..5, vec!["oper"])
+        ], body, vec!["expr"]);
+        assert_eq!(reordered.token_at_byte(4).map(|pt| pt.content()), Some("+ "));
+        assert_eq!(reordered.token_at_byte(0).map(|pt| pt.content()), Some("34"));
+        assert!(reordered.token_at_byte(2).is_none());
+
+        // a zero-width leaf is skipped for the leaf that covers the offset
+        let with_empty = ParseToken::new_branch(vec![leaf(0..2, vec!["int"]), leaf(2..2, vec![]), leaf(2..3, vec!["space"])], body, vec![]);
+        assert_eq!(with_empty.token_at_byte(2).map(|pt| pt.content()), Some(" "));
+        let sentinel = tokens_to_parse_tokens(str_to_tokens(body)).pop().unwrap();
+        assert!(sentinel.token_at_byte(sentinel.content_range().unwrap().start).is_none());
+
+        // at the boundary between two leaves, the one that starts there wins
+        let adjacent = ParseToken::new_branch(vec![leaf(0..1, vec!["digit"]), leaf(1..2, vec!["digit"])], body, vec!["int"]);
+        assert_eq!(adjacent.token_at_byte(1).map(|pt| pt.content()), Some("4"));
+        assert_eq!(adjacent.token_at_byte(0).map(|pt| pt.content()), Some("3"));
+        assert!(adjacent.token_at_byte(2).is_none());
+    }
 }
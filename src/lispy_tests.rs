@@ -1,59 +1,16 @@
 pub use super::parse_token::*;
 pub use super::*;
-pub use blex::token::*;
+pub use super::grouping::*;
+pub use super::rules::*;
+pub use super::position::*;
+pub use super::tree_index::*;
+pub use super::visit::*;
+pub use super::stream::*;
+pub use super::sexpr::*;
 
 
-fn whitespace_rule(mut tokens: Vec<Token>) -> Option<Vec<Token>> {
-    let ch = tokens[0].single_char().unwrap_or_default();
-    if ch.is_whitespace() || ch == '\u{0}' {
-        tokens[0].tags.push("ws");
-    }
-    Some(tokens)
-}
-
-fn paren_rule(mut tokens: Vec<Token>) -> Option<Vec<Token>> {
-    let ch = tokens[0].single_char().unwrap_or_default();
-    if ch == '(' || ch == ')' {
-        tokens[0].tags.push("paren");
-    }
-    Some(tokens)
-}
-
-fn word_rule(tokens: Vec<Token>) -> Option<Vec<Token>> {
-    let empty = &empty_token();
-    let last_token = tokens.last().unwrap_or(empty);
-    if !last_token.has_tag("ws") && !last_token.has_tag("paren") {
-        None
-    } else if tokens.len() == 1 {
-        Some(tokens)
-    } else {
-        Some(vec![
-            wrap(tokens[0..tokens.len() - 1].to_vec(), vec!["word"]),
-            tokens.last().unwrap().clone(),
-        ])
-    }
-}
-
-fn remove_whitespace_rule(tokens: Vec<Token>) -> Option<Vec<Token>> {
-    if tokens[0].has_tag("ws") {
-        Some(vec![])
-    } else {
-        Some(tokens)
-    }
-}
-
-fn s_expr_rules() -> Vec<impl Fn(Vec<Token>) -> Option<Vec<Token>>> {
-    [
-        whitespace_rule,
-        paren_rule,
-        word_rule,
-        remove_whitespace_rule,
-    ]
-    .to_vec()
-}
-
 fn remove_last(mut pts: Vec<ParseToken>) -> Vec<ParseToken> {
-    if pts.len() > 0 {
+    if !pts.is_empty() {
         pts.remove(pts.len() - 1);
     }
     pts
@@ -79,6 +36,26 @@ fn eval(mut pts: Vec<ParseToken>) -> Vec<ParseToken> {
     pts
 }
 
+const RGB_SERIES: &str = "
+(define (rgb-series mk)
+  (vc-append
+   (series (lambda (sz) (colorize (mk sz) \"red\")))
+   (series (lambda (sz) (colorize (mk sz) \"green\")))
+   (series (lambda (sz) (colorize (mk sz) \"blue\")))))";
+
+fn parse(text: &str) -> Vec<ParseToken<'_>> {
+    let mut body = str_to_tokens(text);
+    process_rules(s_expr_rules(), &mut body, false);
+    remove_last(eval(tokens_to_parse_tokens(body)))
+}
+
+fn levels<'t, 'a>(pt: &'t ParseToken<'a>, level: usize, out: &mut Vec<(&'t ParseToken<'a>, usize)>) {
+    out.push((pt, level));
+    for child in pt.children().unwrap_or_default() {
+        levels(child, level + 1, out);
+    }
+}
+
 #[test]
 pub fn parse_s_exprs() {
     let text = "
@@ -114,4 +91,438 @@ pub fn parse_s_exprs2() {
 
     let pts = tokens_to_parse_tokens(body);
     print_parse_tokens(remove_last(eval(pts)));
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn bfs_visits_levels_in_order() {
+    let pts = parse(RGB_SERIES);
+    let root = &pts[0];
+
+    let mut known = vec![];
+    levels(root, 0, &mut known);
+    let order: Vec<usize> = root.iter_bfs()
+        .map(|pt| known.iter().find(|(k, _)| std::ptr::eq(*k, pt)).unwrap().1)
+        .collect();
+
+    assert_eq!(order.len(), known.len());
+    assert!(order.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(order[1..4], [1, 1, 1]);
+}
+
+#[test]
+pub fn count_space_nodes() {
+    let pts = parse("A (space)");
+
+    assert_eq!(pts.len(), 2);
+    assert_eq!(pts[0].node_count(), 1);
+    assert_eq!(pts[1].node_count(), 2);
+    assert_eq!(pts[1].leaf_count(), 1);
+    assert_eq!(pts.iter().map(|pt| pt.leaf_count()).sum::<usize>(), 2);
+    assert_eq!(pts[1].leaf_count(), pts[1].leaves().count());
+}
+
+#[test]
+pub fn find_every_expr() {
+    let pts = parse(RGB_SERIES);
+
+    let exprs = pts[0].find_all_by_tag("expr");
+    assert_eq!(exprs.len(), RGB_SERIES.matches('(').count());
+    assert!(std::ptr::eq(exprs[0], &pts[0]));
+    assert!(exprs.iter().all(|pt| pt.is_branch()));
+}
+
+#[test]
+pub fn dot_is_well_formed() {
+    let pts = parse(RGB_SERIES);
+    let dot = pts[0].to_dot();
+
+    assert!(dot.starts_with("digraph {"));
+    assert_eq!(dot.matches('{').count(), dot.matches('}').count());
+
+    let declared: Vec<&str> = dot.lines()
+        .filter(|l| l.contains("[label="))
+        .map(|l| l.trim().split(' ').next().unwrap())
+        .collect();
+    let unique: std::collections::HashSet<&&str> = declared.iter().collect();
+    assert_eq!(declared.len(), pts[0].node_count());
+    assert_eq!(unique.len(), declared.len());
+
+    let edges: Vec<&str> = dot.lines().filter(|l| l.contains("->")).collect();
+    assert_eq!(edges.len(), pts[0].node_count() - 1);
+    for edge in edges {
+        for end in edge.trim().trim_end_matches(';').split(" -> ") {
+            assert!(declared.contains(&end), "undeclared node {} in {}", end, edge);
+        }
+    }
+}
+
+fn strip_branch_tags(pt: &mut ParseToken) {
+    if let Some(children) = pt.children_mut() {
+        children.iter_mut().for_each(strip_branch_tags);
+        pt.tags.clear();
+    }
+}
+
+#[test]
+pub fn sexpr_round_trip() {
+    let pts = parse("A (space)");
+    assert_eq!(pts[1].to_sexpr(), "(expr space)");
+
+    let mut pts = parse(RGB_SERIES);
+    strip_branch_tags(&mut pts[0]);
+    let printed = pts[0].to_sexpr();
+
+    let mut original = str_to_tokens(RGB_SERIES);
+    process_rules(s_expr_rules(), &mut original, false);
+    let mut relexed = str_to_tokens(&printed);
+    process_rules(s_expr_rules(), &mut relexed, false);
+
+    let original_tags: Vec<Vec<&str>> = original.iter().map(|t| t.tags.clone()).collect();
+    let relexed_tags: Vec<Vec<&str>> = relexed.iter().map(|t| t.tags.clone()).collect();
+    assert_eq!(original_tags, relexed_tags);
+}
+
+#[test]
+pub fn rename_expr_tags() {
+    let mut pts = parse(RGB_SERIES);
+    let exprs = pts[0].find_all_by_tag("expr").len();
+
+    pts[0].map_tags(|t| if t == "expr" { "sexpr".into() } else { t });
+    assert!(pts[0].find_all_by_tag("expr").is_empty());
+    assert_eq!(pts[0].find_all_by_tag("sexpr").len(), exprs);
+    assert_eq!(pts[0].find_all_by_tag("word").len(), pts[0].leaf_count());
+}
+
+#[test]
+pub fn match_brackets_agrees_with_eval() {
+    let mut body = str_to_tokens(RGB_SERIES);
+    process_rules(s_expr_rules(), &mut body, false);
+    let pts = remove_last(tokens_to_parse_tokens(body));
+
+    assert_eq!(match_brackets(pts.clone(), "(", ")", vec!["expr"]).unwrap(), eval(pts));
+}
+
+fn group_rule(pts: Vec<ParseToken>) -> Option<Vec<ParseToken>> {
+    let last = pts.last().unwrap();
+    if !pts[0].has_tag("(") || (pts.len() > 1 && last.has_tag("(")) {
+        Some(pts)
+    } else if !last.has_tag(")") {
+        None
+    } else {
+        let body = pts[0].body;
+        let children = pts[1..pts.len() - 1].to_vec();
+        Some(vec![ParseToken::new_branch(children, body, vec!["expr"])])
+    }
+}
+
+#[test]
+pub fn group_with_parse_rules() {
+    let mut body = str_to_tokens(RGB_SERIES);
+    process_rules(s_expr_rules(), &mut body, false);
+    let mut pts = tokens_to_parse_tokens(body);
+    let expected = eval(pts.clone());
+
+    process_parse_rules(vec![group_rule], &mut pts, false);
+    assert_eq!(pts, expected);
+}
+
+#[test]
+pub fn combine_tagging_rules() {
+    let text = "A (space)\n";
+
+    let mut separate = str_to_tokens(text);
+    process_rules(vec![whitespace_rule, paren_rule], &mut separate, false);
+
+    let mut combined = str_to_tokens(text);
+    process_rule(rule_or(vec![whitespace_rule, paren_rule]), &mut combined);
+    assert_eq!(combined, separate);
+    assert!(combined[1].has_tag("ws"));
+    assert!(combined[2].has_tag("paren"));
+
+    let mut sequenced = str_to_tokens(text);
+    process_rule(rule_seq(vec![whitespace_rule, paren_rule, remove_whitespace_rule]), &mut sequenced);
+    assert!(sequenced.iter().all(|t| !t.has_tag("ws")));
+    assert_eq!(sequenced.len(), 8);
+}
+
+#[test]
+pub fn define_on_line_two() {
+    let pts = parse(RGB_SERIES);
+    let idx = LineIndex::new(RGB_SERIES);
+
+    let define = pts[0].find_by(|pt| pt.is_leaf() && pt.content() == "define").unwrap();
+    assert_eq!(define.position(&idx), Some((2, 2)));
+    assert_eq!(pts[0].position(&idx), Some((2, 2)));
+
+    let vc_append = pts[0].find_by(|pt| pt.content() == "vc-append").unwrap();
+    assert_eq!(vc_append.position(&idx), Some((3, 4)));
+
+    assert_eq!(idx.line_col(0), (1, 1));
+    assert_eq!(idx.line_col(1), (2, 1));
+    assert_eq!(LineIndex::new("café\nx").line_col(6), (2, 1));
+    assert_eq!(LineIndex::new("café\nx").line_col(5), (1, 5));
+}
+
+#[test]
+pub fn ancestors_of_lambda() {
+    let pts = parse(RGB_SERIES);
+    let index = TreeIndex::new(&pts[0]);
+
+    let lambda = pts[0].find_by(|pt| pt.is_leaf() && pt.content() == "lambda").unwrap();
+    let id = index.id_of(lambda).unwrap();
+    let chain: Vec<&str> = index.ancestors(id).iter()
+        .map(|&a| index.node(a).unwrap().content().split_whitespace().next().unwrap())
+        .collect();
+
+    assert_eq!(chain, vec!["lambda", "series", "vc-append", "define"]);
+    assert_eq!(index.ancestors(0), Vec::<usize>::new());
+    assert_eq!(index.parent(0), None);
+    assert_eq!(index.len(), pts[0].node_count());
+}
+
+#[test]
+pub fn prune_whitespace_leaves() {
+    let mut body = str_to_tokens(RGB_SERIES);
+    process_rules(vec![whitespace_rule, paren_rule, word_rule], &mut body, false);
+    let pts = remove_last(eval(tokens_to_parse_tokens(body)));
+    let mut tree = ParseToken::new_branch_from_first(pts.clone(), vec!["program"]);
+    let has_ws = |pt: &ParseToken| pt.iter_preorder().any(|t| t.has_tag("ws"));
+    assert!(has_ws(&tree));
+
+    tree.prune_by(|pt| pt.has_tag("ws"));
+    assert!(!has_ws(&tree));
+    let expected = ParseToken::new_branch_from_first(parse(RGB_SERIES), vec!["program"]);
+    assert_eq!(tree, expected);
+
+    let mut leaf = pts[0].clone();
+    leaf.prune_by(|_| true);
+    assert_eq!(leaf, pts[0]);
+}
+
+#[test]
+pub fn trimmed_program_content() {
+    let mut tokens = str_to_tokens(RGB_SERIES);
+    tokens.pop();
+    let program = ParseToken::new_branch_from_first(
+        tokens_to_parse_tokens_maybe_sentinel(tokens, false),
+        vec!["program"]);
+
+    assert!(program.content().starts_with('\n'));
+    assert!(program.content_trimmed().starts_with("(define"));
+    assert!(program.content_trimmed().ends_with("\"blue\")))))"));
+
+    let lines: Vec<&str> = program.content_lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[1], "(define (rgb-series mk)");
+}
+
+#[test]
+pub fn count_expr_tags() {
+    let pts = parse(RGB_SERIES);
+    let groups = RGB_SERIES.matches('(').count();
+    let counts = pts[0].tag_histogram();
+    assert_eq!(counts["expr"], groups);
+    assert_eq!(counts.get("paren"), None);
+    assert_eq!(counts["word"], pts[0].find_all_by_tag("word").len());
+}
+
+#[test]
+pub fn trace_group_rule() {
+    let mut body = str_to_tokens("(a (b c))");
+    process_rules(s_expr_rules(), &mut body, false);
+    let mut pts = tokens_to_parse_tokens(body);
+
+    let traces = process_parse_rules_traced(vec![group_rule], &mut pts);
+    let steps: Vec<(usize, usize, String, String)> = traces.iter().map(|t| (
+        t.rule_index,
+        t.start,
+        t.input.iter().map(|pt| pt.to_sexpr()).collect::<Vec<String>>().join(" "),
+        t.output.iter().map(|pt| pt.to_sexpr()).collect()
+    )).collect();
+    assert_eq!(steps, vec![
+        (0, 2, "( b c )".to_string(), "(expr b c)".to_string()),
+        (0, 0, "( a (expr b c) )".to_string(), "(expr a (expr b c))".to_string())
+    ]);
+}
+
+#[test]
+pub fn display_two_levels() {
+    let pts = parse(RGB_SERIES);
+    let shown = pts[0].to_string_max_depth(1);
+    let lines: Vec<&str> = shown.lines().collect();
+    assert_eq!(lines, vec![
+        "(\"expr\"): \"define (rgb-series mk)\\n  (vc-append\\n   (…\"",
+        "\tdefine (\"word\")",
+        "\t(\"expr\"): […2 children…]",
+        "\t(\"expr\"): […4 children…]"
+    ]);
+    assert_eq!(pts[0].to_string_max_depth(usize::MAX), pts[0].to_string());
+}
+
+#[test]
+pub fn tag_top_level_exprs() {
+    let mut pts = parse(RGB_SERIES);
+    let mut root = true;
+    pts[0].walk_mut(|pt| {
+        if std::mem::take(&mut root) {
+            Descent::Into
+        } else {
+            if pt.is_branch() {
+                pt.add_tag("top");
+            }
+            Descent::Skip
+        }
+    });
+    assert_eq!(pts[0].find_all_by_tag("top").len(), 2);
+    assert!(!pts[0].has_tag("top"));
+
+    let mut visited = 0;
+    pts[0].walk_mut(|_| {
+        visited += 1;
+        if visited == 3 { Descent::Stop } else { Descent::Into }
+    });
+    assert_eq!(visited, 3);
+}
+
+#[test]
+pub fn stream_hides_sentinel() {
+    let mut body = str_to_tokens(RGB_SERIES);
+    process_rules(s_expr_rules(), &mut body, false);
+
+    let mut grouped = ParseStream::from_tokens(body.clone());
+    grouped.match_brackets("(", ")", vec!["expr"]).unwrap();
+    assert_eq!(grouped.tokens(), &parse(RGB_SERIES)[..]);
+
+    let mut ruled = ParseStream::from_tokens(body);
+    ruled.apply_rules(vec![group_rule]);
+    assert_eq!(ruled.tokens(), grouped.tokens());
+
+    let lexed = ParseStream::from_tokens(str_to_tokens("(a b)"));
+    for stream in [&grouped, &ruled, &lexed] {
+        assert!(stream.tokens().iter().all(|pt| !pt.content().is_empty()));
+    }
+    assert_eq!(lexed.tokens().len(), 5);
+    assert_eq!(lexed.into_tokens().len(), 5);
+
+    // an empty token of the user's own before the sentinel is kept
+    let mut tokens = str_to_tokens("(a b)");
+    tokens.insert(tokens.len() - 1, Token { body: "(a b)", indices: 5..5, tags: vec!["eof"] });
+    let mut with_empty = ParseStream::from_tokens(tokens);
+    assert_eq!(with_empty.tokens().len(), 6);
+    with_empty.apply_rules(vec![group_rule]);
+    assert!(with_empty.tokens().last().unwrap().has_tag("eof"));
+    with_empty.match_brackets("(", ")", vec!["expr"]).unwrap();
+    assert!(with_empty.tokens().last().unwrap().has_tag("eof"));
+}
+
+#[test]
+pub fn preserve_whitespace() {
+    let text = "A (space)";
+    let mut body = str_to_tokens(text);
+    process_rules(s_expr_rules_preserving(), &mut body, false);
+    // the sentinel from str_to_tokens is kept too, tagged "ws"
+    let pts = remove_last(tokens_to_parse_tokens_maybe_sentinel(body, false));
+
+    assert!(pts.iter().any(|pt| pt.has_tag("ws")));
+    assert_eq!(pts.iter().map(|pt| pt.to_source()).collect::<String>(), text);
+
+    let grouped = match_brackets(pts.clone(), "(", ")", vec!["expr"]).unwrap();
+    assert_eq!(grouped.len(), 3);
+    assert_eq!(grouped[1].to_source(), " ");
+    assert_eq!(grouped, eval(pts));
+}
+
+#[test]
+pub fn nearest_enclosing_expr() {
+    let pts = parse(RGB_SERIES);
+    let index = TreeIndex::new(&pts[0]);
+
+    let colorize = pts[0].find_by(|pt| pt.is_leaf() && pt.content() == "colorize").unwrap();
+    let id = index.id_of(colorize).unwrap();
+    let expr = index.nearest_ancestor_with_tag(id, "expr").unwrap();
+
+    assert_eq!(Some(expr), index.parent(id));
+    assert_eq!(index.node(expr).unwrap().content(), "colorize (mk sz) \"red\"");
+    assert_eq!(index.nearest_ancestor_with_tag(id, "word"), None);
+    assert_eq!(index.nearest_ancestor_with_tag(0, "expr"), None);
+}
+
+#[test]
+pub fn preview_define() {
+    let pts = parse(RGB_SERIES);
+    assert_eq!(pts[0].content_preview(20), "define (rgb-series m…");
+    assert_eq!(pts[0].content_preview(20).chars().count(), 21);
+    assert_eq!(pts[0].nth_child(0).unwrap().content_preview(20), "define");
+
+    let body = "déjà vu";
+    let leaf = ParseToken::new_leaf(Token{body, indices: 0..body.len(), tags: vec![]});
+    assert_eq!(leaf.content_preview(3), "déj…");
+}
+
+#[test]
+pub fn tagging_rules_from_spec() {
+    let rules = rules_from_spec("
+        ws: whitespace | end
+        paren: '(' | ')'
+    ").unwrap();
+    assert_eq!(rules.len(), 2);
+
+    let mut from_spec = str_to_tokens(RGB_SERIES);
+    process_rules(rules, &mut from_spec, false);
+    let mut by_hand = str_to_tokens(RGB_SERIES);
+    process_rules(vec![whitespace_rule, paren_rule], &mut by_hand, false);
+    assert_eq!(from_spec, by_hand);
+
+    let err = rules_from_spec("ws: whitespace\nparen: '(' | paren").err().unwrap();
+    assert_eq!(err.message, "unknown pattern \"paren\"");
+    assert_eq!(err.span, 28..33);
+    assert_eq!(rules_from_spec("ws whitespace").err().unwrap().span, 0..13);
+
+    let spec = String::from("ws: whitespace | end\nparen: '(' | ')'");
+    let mut from_owned = str_to_tokens(RGB_SERIES);
+    process_rules(rules_from_spec_owned(&spec).unwrap(), &mut from_owned, false);
+    drop(spec);
+    assert_eq!(from_owned, by_hand);
+
+    // a second spec with the same tag reuses its copy rather than leaking another
+    let mut again = str_to_tokens(RGB_SERIES);
+    process_rules(rules_from_spec_owned(&String::from("paren: '('")).unwrap(), &mut again, false);
+    let paren_tag = |tokens: &[Token<'static>]| tokens.iter()
+        .find_map(|t| t.tags.iter().copied().find(|&tag| tag == "paren"))
+        .unwrap();
+    assert!(std::ptr::eq(paren_tag(&from_owned), paren_tag(&again)));
+}
+
+#[test]
+pub fn collect_word_text() {
+    let root = ParseToken::new_branch_from_first(parse("A (space)"), vec!["root"]);
+    assert_eq!(root.collect_text_by_tag("word"), vec!["A", "space"]);
+    assert_eq!(root.collect_text_by_tag("expr"), vec!["space"]);
+    assert!(root.collect_text_by_tag("missing").is_empty());
+
+    let mut reversed = root.clone();
+    reversed.children_mut().unwrap().reverse();
+    assert_eq!(reversed.collect_text_by_tag("word"), vec!["A", "space"]);
+}
+
+#[test]
+pub fn merge_spans() {
+    let pts = parse(RGB_SERIES);
+    let idx = LineIndex::new(RGB_SERIES);
+    let define = pts[0].find_by(|pt| pt.is_leaf() && pt.content() == "define").unwrap();
+    let vc_append = pts[0].find_by(|pt| pt.content() == "vc-append").unwrap();
+
+    let (a, b) = (define.span().unwrap(), vc_append.span().unwrap());
+    assert_eq!(a.text(), "define");
+    assert_eq!(b.line_col(&idx), (3, 4));
+
+    let merged = b.merge(&a);
+    assert_eq!(merged, a.merge(&b));
+    assert!(merged.range.start <= merged.range.end);
+    assert!(merged.text().starts_with("define") && merged.text().ends_with("vc-append"));
+    assert_eq!(merged.line_col(&idx), (2, 2));
+
+    let reversed = Span { body: RGB_SERIES, range: std::ops::Range { start: 10, end: 4 } };
+    assert_eq!(reversed.merge(&reversed).range, 4..10);
+    assert_eq!(Span::new(RGB_SERIES, 2..3).merge(&reversed).range, 2..10);
+}
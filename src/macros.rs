@@ -0,0 +1,64 @@
+/// Builds a tree of parse tokens from a nested description. A leaf is written
+/// as `leaf(text, [tags...])`, where `text` is a string literal, and a branch
+/// is written as `[tags...] { children... }`. Every token in the tree borrows
+/// one body, made by joining the text of the leaves in order with single
+/// spaces, so [ParseToken::content] of a branch spans its leaves as it would
+/// in a parsed tree.
+///
+/// [ParseToken::content]: crate::parse_token::ParseToken::content
+///
+/// ```
+/// # use blarse::parse_tree;
+/// let tree = parse_tree!(["expr", "addExpr"] {
+///     leaf("34", ["int"]),
+///     leaf("+", ["oper", "plus"]),
+///     ["expr"] { leaf("35", ["int"]) }
+/// });
+/// assert_eq!(tree.leaf_count(), 3);
+/// assert_eq!(tree.content(), "34 + 35");
+/// ```
+#[macro_export]
+macro_rules! parse_tree {
+    // the shared body, built at compile time
+    (@body leaf($text:literal, [$($tag:expr),* $(,)?])) => { $text };
+    (@body [$($tag:expr),* $(,)?] { $($children:tt)* }) => {
+        $crate::parse_tree!(@join $($children)*)
+    };
+    (@join) => { "" };
+    (@join $head:tt $rest:tt $(,)?) => { $crate::parse_tree!(@body $head $rest) };
+    (@join $head:tt $rest:tt, $($more:tt)+) => {
+        concat!($crate::parse_tree!(@body $head $rest), " ", $crate::parse_tree!(@join $($more)+))
+    };
+
+    // the tokens, with `pos` tracking where the next leaf starts in `body`
+    (@build $body:ident, $pos:ident; leaf($text:literal, [$($tag:expr),* $(,)?])) => {{
+        let start = *$pos;
+        *$pos += $text.len();
+        $crate::parse_token::ParseToken::new_leaf($crate::Token {
+            body: $body,
+            indices: start..*$pos,
+            tags: vec![$($tag),*]
+        })
+    }};
+    (@build $body:ident, $pos:ident; [$($tag:expr),* $(,)?] { $($children:tt)* }) => {{
+        #[allow(unused_mut)]
+        let mut children: Vec<$crate::parse_token::ParseToken> = vec![];
+        $crate::parse_tree!(@children $body, $pos, children; $($children)*);
+        $crate::parse_token::ParseToken::new_branch(children, $body, vec![$($tag),*])
+    }};
+    (@children $body:ident, $pos:ident, $out:ident; $(,)?) => {};
+    (@children $body:ident, $pos:ident, $out:ident; $head:tt $rest:tt $(, $($more:tt)*)?) => {
+        // leaves are separated by a space in the body, as in `@join`
+        if !$out.is_empty() {
+            *$pos += 1;
+        }
+        $out.push($crate::parse_tree!(@build $body, $pos; $head $rest));
+        $crate::parse_tree!(@children $body, $pos, $out; $($($more)*)?);
+    };
+
+    ($($tree:tt)+) => {{
+        let body: &'static str = $crate::parse_tree!(@body $($tree)+);
+        let pos = &mut 0usize;
+        $crate::parse_tree!(@build body, pos; $($tree)+)
+    }};
+}
@@ -0,0 +1,65 @@
+use super::parse_token::*;
+
+/// The owned counterpart of [ParseNode]. A leaf's text is stored in its
+/// token's `content`, so it needs no range.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParseNodeOwned {
+    Leaf,
+    Branch(Vec<ParseTokenOwned>)
+}
+
+/// A tree of tokens like [ParseToken], but owning its content and tags instead
+/// of borrowing them from a body. This lets a tree outlive the string it was
+/// parsed from. With the `serde` feature, this is what a serialized
+/// [ParseToken] deserializes into.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseTokenOwned {
+    pub node: ParseNodeOwned,
+    pub content: String,
+    pub tags: Vec<String>
+}
+
+impl ParseTokenOwned {
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        matches!(self.node, ParseNodeOwned::Leaf)
+    }
+
+    pub fn is_branch(&self) -> bool {
+        matches!(self.node, ParseNodeOwned::Branch(_))
+    }
+
+    /// If this token is a branch, returns its children. Otherwise, returns None.
+    pub fn children(&self) -> Option<&[ParseTokenOwned]> {
+        match &self.node {
+            ParseNodeOwned::Leaf => None,
+            ParseNodeOwned::Branch(children) => Some(children)
+        }
+    }
+}
+
+impl<'a> ParseToken<'a> {
+    /// Copies this tree into a [ParseTokenOwned], which doesn't borrow from
+    /// `body`. Each token keeps the text it spans as its content.
+    pub fn to_owned(&self) -> ParseTokenOwned {
+        ParseTokenOwned {
+            node: match &self.node {
+                ParseNode::Leaf(_) => ParseNodeOwned::Leaf,
+                ParseNode::Branch(children) => {
+                    ParseNodeOwned::Branch(children.iter().map(|c| c.to_owned()).collect())
+                }
+            },
+            content: self.content().to_string(),
+            tags: self.tags.iter().map(|t| t.to_string()).collect()
+        }
+    }
+}
@@ -1,7 +1,10 @@
 pub use blex::*;
-use blex::empty_token;
 pub use super::parse_token;
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::IsTerminal;
 use std::ops::Range;
 
 #[derive(Clone)]
@@ -18,13 +21,138 @@ pub enum ParseNode<'a> {
 pub struct ParseToken<'a> {
     pub node: ParseNode<'a>,
     pub body: &'a str,
-    pub tags: Vec<&'a str>
+    /// Tags are usually borrowed from the source (as string literals in rules),
+    /// but can be owned strings built while rewriting a tree.
+    pub tags: Vec<Cow<'a, str>>
 }
 
 impl fmt::Display for ParseToken<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.write_indented(0, f)?;
-        Ok(())
+        self.write_with_options(f, &DisplayOptions::default())
+    }
+}
+
+impl fmt::Debug for ParseToken<'_> {
+    /// Shows the kind of node, its tags, and the text it spans, along with a
+    /// branch's children. Use `{:#?}` to spread it over several lines.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.node {
+            ParseNode::Leaf(_) => f.debug_struct("Leaf")
+                .field("tags", &self.tag_strs())
+                .field("content", &self.content())
+                .finish(),
+            ParseNode::Branch(children) => f.debug_struct("Branch")
+                .field("tags", &self.tag_strs())
+                .field("content", &self.content())
+                .field("children", children)
+                .finish()
+        }
+    }
+}
+
+/// Controls how [ParseToken::write_with_options] lays out a tree. The default
+/// options are the ones used by [fmt::Display].
+#[derive(Clone, Debug)]
+pub struct DisplayOptions<'o> {
+    /// The string written once per level of indentation.
+    pub indent: &'o str,
+    /// How many characters of a branch's content to show on its header line
+    /// before cutting it off with an ellipsis.
+    pub content_width: usize,
+    /// Whether to color tags and content with ANSI escape codes. Only has an
+    /// effect when the `colored` feature is enabled.
+    pub color: bool,
+    /// How many levels below the root to show. Branches at the deepest level
+    /// shown are summarized by how many children they have. None shows the
+    /// whole tree.
+    pub max_depth: Option<usize>
+}
+
+impl Default for DisplayOptions<'_> {
+    fn default() -> Self {
+        DisplayOptions { indent: "\t", content_width: 40, color: false, max_depth: None }
+    }
+}
+
+const TAG_COLOR: &str = "\x1b[36m";
+const CONTENT_COLOR: &str = "\x1b[33m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// Wraps a piece of display output in an ANSI color, if coloring is on.
+fn paint(text: String, color: &str, options: &DisplayOptions) -> String {
+    if cfg!(feature = "colored") && options.color {
+        format!("{}{}{}", color, text, RESET_COLOR)
+    } else {
+        text
+    }
+}
+
+impl PartialEq for ParseToken<'_> {
+    /// Two parse tokens are equal if they have the same tags and the same
+    /// structure, with leaves compared by their content rather than by which
+    /// body or range they borrow from.
+    fn eq(&self, other: &Self) -> bool {
+        self.tags == other.tags && match (&self.node, &other.node) {
+            (ParseNode::Leaf(_), ParseNode::Leaf(_)) => {
+                self.content() == other.content()
+            },
+            (ParseNode::Branch(a), ParseNode::Branch(b)) => a == b,
+            _ => false
+        }
+    }
+}
+
+impl Eq for ParseToken<'_> {}
+
+impl Hash for ParseToken<'_> {
+    /// Consistent with [PartialEq]: hashes the tags and the structure of the
+    /// tree, with leaves hashed by their content.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.tags.hash(state);
+        match &self.node {
+            ParseNode::Leaf(_) => {
+                0u8.hash(state);
+                self.content().hash(state);
+            },
+            ParseNode::Branch(children) => {
+                1u8.hash(state);
+                children.hash(state);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ParseToken<'_> {
+    /// Writes the token's node, content, and tags, with the content resolved
+    /// from its body, so the result doesn't need the body to be read.
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = s.serialize_struct("ParseToken", 3)?;
+        state.serialize_field("node", &self.node)?;
+        state.serialize_field("content", self.content())?;
+        state.serialize_field("tags", &self.tags)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ParseNode<'_> {
+    /// A leaf's range is left out, since its token's content is written
+    /// instead.
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ParseNode::Leaf(_) => s.serialize_unit_variant("ParseNode", 0, "Leaf"),
+            ParseNode::Branch(children) => s.serialize_newtype_variant("ParseNode", 1, "Branch", children)
+        }
+    }
+}
+
+impl<'a> From<Token<'a>> for ParseToken<'a> {
+    /// See [ParseToken::new_leaf]. A vector of tokens can be turned into leaves
+    /// with `tokens.into_iter().map(ParseToken::from).collect()`.
+    fn from(tok: Token<'a>) -> Self {
+        ParseToken::new_leaf(tok)
     }
 }
 
@@ -35,18 +163,69 @@ pub fn print_parse_tokens(tokens: Vec<ParseToken>) {
 }
 
 impl <'a> ParseToken<'a> {
-    fn write_indented(&self, tabs: usize, f: &mut fmt::Formatter) -> fmt::Result {
+    /// Writes this tree in the same indented format as [fmt::Display], but using
+    /// the given string (for example, two spaces) for each level of indentation.
+    pub fn write_with<W: fmt::Write>(&self, f: &mut W, indent: &str) -> fmt::Result {
+        self.write_with_options(f, &DisplayOptions { indent, ..DisplayOptions::default() })
+    }
+
+    /// Writes this tree in the same indented format as [fmt::Display], laid out
+    /// according to the given options.
+    pub fn write_with_options<W: fmt::Write>(&self, f: &mut W, options: &DisplayOptions) -> fmt::Result {
+        self.write_indented(0, options, f)
+    }
+
+    /// Renders this tree like [fmt::Display], with tags and content colored
+    /// using ANSI escape codes. Without the `colored` feature, this is the same
+    /// as `to_string()`.
+    pub fn to_colored_string(&self) -> String {
+        let mut out = String::new();
+        // writing to a String never fails
+        self.write_with_options(&mut out, &DisplayOptions { color: true, ..DisplayOptions::default() }).unwrap();
+        out
+    }
+
+    /// Prints this tree to standard output, colored as in
+    /// [ParseToken::to_colored_string] only if standard output is a terminal.
+    pub fn print_colored(&self) {
+        let color = std::io::stdout().is_terminal();
+        let mut out = String::new();
+        // writing to a String never fails
+        self.write_with_options(&mut out, &DisplayOptions { color, ..DisplayOptions::default() }).unwrap();
+        print!("{}", out);
+    }
+
+    /// Renders this tree like [fmt::Display], but only `max` levels deep; see
+    /// [DisplayOptions::max_depth].
+    pub fn to_string_max_depth(&self, max: usize) -> String {
+        let mut out = String::new();
+        // writing to a String never fails
+        self.write_with_options(&mut out, &DisplayOptions { max_depth: Some(max), ..DisplayOptions::default() }).unwrap();
+        out
+    }
+
+    fn write_indented<W: fmt::Write>(&self, tabs: usize, options: &DisplayOptions, f: &mut W) -> fmt::Result {
         for _ in 0..tabs {
-            write!(f, "\t")?;
+            f.write_str(options.indent)?;
         }
         match &self.node {
-            ParseNode::Leaf(r) => {
-                writeln!(f, "{0} {1}", &self.body[r.clone()], format_tags(self.tags.clone()))?;
+            ParseNode::Leaf(_) => {
+                writeln!(f, "{0} {1}",
+                    paint(self.content().to_string(), CONTENT_COLOR, options),
+                    paint(format_tags(self.tag_strs()), TAG_COLOR, options))?;
+            },
+            ParseNode::Branch(children) if options.max_depth.is_some_and(|max| tabs >= max) => {
+                writeln!(f, "{}: […{} children…]",
+                    paint(format_tags(self.tag_strs()), TAG_COLOR, options),
+                    children.len())?;
             },
             ParseNode::Branch(children) => {
-                writeln!(f, "{}:", format_tags(self.tags.clone()))?;
+                let shown = self.content_preview(options.content_width);
+                writeln!(f, "{}: {}",
+                    paint(format_tags(self.tag_strs()), TAG_COLOR, options),
+                    paint(format!("{:?}", shown), CONTENT_COLOR, options))?;
                 for pt in children {
-                    pt.write_indented(tabs + 1, f)?;
+                    pt.write_indented(tabs + 1, options, f)?;
                 }
             }
         }
@@ -55,9 +234,9 @@ impl <'a> ParseToken<'a> {
 
     pub fn new_leaf(tok: Token<'a>) -> ParseToken<'a> {
         ParseToken { 
-            node: ParseNode::Leaf(tok.indices.clone()), 
+            node: ParseNode::Leaf(tok.indices), 
             body: tok.body, 
-            tags: tok.tags.clone() 
+            tags: borrowed_tags(tok.tags)
         }
     }
 
@@ -65,46 +244,334 @@ impl <'a> ParseToken<'a> {
         ParseToken {
             node: ParseNode::Branch(children),
             body,
-            tags
+            tags: borrowed_tags(tags)
         }
     }
 
+    /// Creates a branch that borrows its body from its first child.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `children` is empty. See [ParseToken::try_new_branch_from_first]
+    /// for a non-panicking version.
     pub fn new_branch_from_first(children: Vec<ParseToken<'a>>, tags:Vec<&'a str>) -> ParseToken<'a> {
-        let body = children[0].body;
-        ParseToken {
+        ParseToken::try_new_branch_from_first(children, tags)
+            .expect("cannot take the body of the first child of an empty branch")
+    }
+
+    /// Creates a branch that borrows its body from its first child, or returns
+    /// None if `children` is empty.
+    pub fn try_new_branch_from_first(children: Vec<ParseToken<'a>>, tags:Vec<&'a str>) -> Option<ParseToken<'a>> {
+        let body = children.first()?.body;
+        Some(ParseToken {
             node: ParseNode::Branch(children),
             body,
-            tags
+            tags: borrowed_tags(tags)
+        })
+    }
+
+    /// Groups trees under a new branch that borrows its body from the first of
+    /// them. Returns None if `children` is empty, or if the children don't all
+    /// borrow the same body, since the branch's content couldn't be found in a
+    /// single body.
+    pub fn join(children: Vec<ParseToken<'a>>, tags: Vec<&'a str>) -> Option<ParseToken<'a>> {
+        let body = children.first()?.body;
+        if children.iter().any(|c| !std::ptr::eq(c.body, body)) {
+            return None;
         }
+        ParseToken::try_new_branch_from_first(children, tags)
     }
 
+    /// The text spanned by this token. See [ParseToken::content_range]. A
+    /// range that is reversed (with its start after its end), runs past the
+    /// end of `body`, or doesn't fall on character boundaries yields an empty
+    /// string instead of panicking.
     pub fn content(&'a self) -> &'a str {
-        if let Some(cr) = self.content_range() {
-            &self.body[cr]
-        } else {
-            ""
+        match self.content_range() {
+            Some(cr) => self.body.get(cr).unwrap_or_default(),
+            None => ""
+        }
+    }
+
+    /// The length in bytes of this token's content. See [ParseToken::content];
+    /// a range that yields an empty string there has a length of 0.
+    pub fn content_len(&self) -> usize {
+        match self.content_range() {
+            Some(cr) => self.body.get(cr).map_or(0, str::len),
+            None => 0
+        }
+    }
+
+    /// See [ParseToken::content]. Returns the content's bytes.
+    pub fn content_bytes(&'a self) -> &'a [u8] {
+        self.content().as_bytes()
+    }
+
+    /// See [ParseToken::content]. Leading and trailing whitespace is removed.
+    pub fn content_trimmed(&'a self) -> &'a str {
+        self.content().trim()
+    }
+
+    /// The lines of this token's content, as in [str::lines].
+    pub fn content_lines(&'a self) -> std::str::Lines<'a> {
+        self.content().lines()
+    }
+
+    /// Up to `max_chars` characters of this token's content, followed by an
+    /// ellipsis (`…`) if any were cut off. Counts characters rather than bytes,
+    /// so multibyte content is never split.
+    pub fn content_preview(&self, max_chars: usize) -> String {
+        let content = self.content();
+        let mut shown: String = content.chars().take(max_chars).collect();
+        if shown.len() < content.len() {
+            shown.push('…');
+        }
+        shown
+    }
+
+    /// Parses this leaf's content as an integer. Returns None for a branch or
+    /// for text that isn't an integer.
+    pub fn as_i64(&self) -> Option<i64> {
+        match &self.node {
+            ParseNode::Leaf(_) => self.content().parse().ok(),
+            ParseNode::Branch(_) => None
+        }
+    }
+
+    /// Parses this leaf's content as a floating-point number. Returns None for
+    /// a branch or for text that isn't a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match &self.node {
+            ParseNode::Leaf(_) => self.content().parse().ok(),
+            ParseNode::Branch(_) => None
+        }
+    }
+
+    /// Reconstructs the text of this subtree by concatenating the content of
+    /// its leaves in order. Unlike [ParseToken::content], this doesn't assume
+    /// that the children are contiguous in `body`, so it is faithful to trees
+    /// whose children have been rearranged.
+    pub fn to_source(&self) -> String {
+        match &self.node {
+            ParseNode::Leaf(_) => self.content().to_string(),
+            ParseNode::Branch(children) => children.iter().map(|c| c.to_source()).collect()
         }
     }
 
+    /// The span of this subtree in `body`: a leaf's own range, or, for a
+    /// branch, the smallest range enclosing the ranges of all of its children.
+    /// If the children are not contiguous (for example, after a rewrite), the
+    /// span includes the gaps between them; see [ParseToken::to_source] for the
+    /// text of the leaves alone. For a branch, `start <= end` is guaranteed.
+    /// Returns None for a branch with no leaves.
     pub fn content_range(&'a self) -> Option<Range<usize>> {
+        if let ParseNode::Leaf(inds) = &self.node {
+            return Some(inds.clone());
+        }
+        // Walk the leaves with an explicit stack, so that very deep trees
+        // can't overflow the call stack
+        let mut range: Option<Range<usize>> = None;
+        let mut stack = vec![self];
+        while let Some(pt) = stack.pop() {
+            match &pt.node {
+                ParseNode::Leaf(r) => {
+                    range = Some(match range {
+                        Some(a) => a.start.min(r.start)..a.end.max(r.end),
+                        None => r.clone()
+                    });
+                },
+                ParseNode::Branch(children) => stack.extend(children)
+            }
+        }
+        range
+    }
+
+    /// Like `==`, but ignores tags: two trees are structurally equal if they
+    /// have the same shape and the same leaf content.
+    pub fn structural_eq(&self, other: &ParseToken) -> bool {
+        match (&self.node, &other.node) {
+            (ParseNode::Leaf(_), ParseNode::Leaf(_)) => self.content() == other.content(),
+            (ParseNode::Branch(a), ParseNode::Branch(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.structural_eq(y))
+            },
+            _ => false
+        }
+    }
+
+    /// Compares two tokens by where they appear in the source: by the start of
+    /// their [ParseToken::content_range], then by its end. Tokens without a
+    /// range (such as empty branches) sort after all others. This is not an
+    /// [Ord] impl because [PartialEq] compares content, not position; sort
+    /// with `v.sort_by(ParseToken::cmp_position)`.
+    pub fn cmp_position(&self, other: &ParseToken) -> Ordering {
+        let key = |pt: &ParseToken| pt.content_range().map(|r| (r.start, r.end));
+        match (key(self), key(other)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal
+        }
+    }
+
+    /// See [ParseToken::content_range]. For a branch, only the children that
+    /// aren't tagged `"ws"` are spanned, so whitespace at either end of the
+    /// branch is left out. Returns None for a branch with only whitespace.
+    pub fn inner_content_range(&self) -> Option<Range<usize>> {
         match &self.node {
-            ParseNode::Leaf(inds) => {
-                Some(inds.clone())
+            ParseNode::Leaf(r) => Some(r.clone()),
+            ParseNode::Branch(children) => children.iter()
+                .filter(|c| !c.has_tag("ws"))
+                .filter_map(|c| c.content_range())
+                .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Adds a tag to this token, unless the token already has it. The tag can
+    /// be borrowed or an owned `String`.
+    pub fn add_tag<T: Into<Cow<'a, str>>>(&mut self, tag: T) {
+        let tag = tag.into();
+        if !self.has_tag(&tag) {
+            self.tags.push(tag);
+        }
+    }
+
+    /// Removes every occurrence of a tag from this token.
+    pub fn remove_tag(&mut self, tag: &str) {
+        self.tags.retain(|t| t != tag);
+    }
+
+    /// This token's tags, separated by `sep`.
+    pub fn tags_joined(&self, sep: &str) -> String {
+        self.tags.join(sep)
+    }
+
+    /// This token's first tag, which [ParseToken::to_sexpr] and
+    /// [ParseToken::to_compact] treat as the kind of token it is.
+    pub fn first_tag(&self) -> Option<&str> {
+        self.tags.first().map(|t| t.as_ref())
+    }
+
+    /// This token's tags as plain string slices.
+    pub fn tag_strs(&self) -> Vec<&str> {
+        self.tags.iter().map(|t| t.as_ref()).collect()
+    }
+
+    /// Recovers a vector of tokens from the leaves of this tree, in order. This
+    /// is roughly the inverse of [tokens_to_parse_tokens]. The tags of branches
+    /// are dropped; see [ParseToken::flatten_to_tokens_inheriting] to keep them.
+    /// The tokens' tags borrow from this tree, since some of them may be owned.
+    pub fn flatten_to_tokens(&self) -> Vec<Token<'_>> {
+        let mut tokens = vec![];
+        self.push_tokens(&[], false, &mut tokens);
+        tokens
+    }
+
+    /// See [ParseToken::flatten_to_tokens]. Each token also receives the tags
+    /// of every branch containing its leaf, innermost first, after its own.
+    /// Tags are not duplicated.
+    pub fn flatten_to_tokens_inheriting(&self) -> Vec<Token<'_>> {
+        let mut tokens = vec![];
+        self.push_tokens(&[], true, &mut tokens);
+        tokens
+    }
+
+    fn push_tokens<'s>(&'s self, inherited: &[&'s str], inherit: bool, tokens: &mut Vec<Token<'s>>) {
+        match &self.node {
+            ParseNode::Leaf(r) => {
+                let mut tags = self.tag_strs();
+                for tag in inherited.iter().rev() {
+                    if !tags.contains(tag) {
+                        tags.push(tag);
+                    }
+                }
+                tokens.push(Token { body: self.body, indices: r.clone(), tags });
             },
             ParseNode::Branch(children) => {
-                let known_ranges: Vec<Range<usize>> = children.iter()
-                    .flat_map(|item| item.content_range()).collect();
-                if let Some(last) = known_ranges.last() {
-                    Some(known_ranges[0].start..last.end)
-                } else {
-                    None
+                let mut inherited = inherited.to_vec();
+                if inherit {
+                    inherited.extend(self.tags.iter().map(|t| t.as_ref()));
+                }
+                for child in children {
+                    child.push_tokens(&inherited, inherit, tokens);
                 }
             }
         }
     }
 
-    pub fn has_tag(&self, tag: &str) -> bool {
-        self.tags.contains(&tag)
+    /// The token this leaf was made from, the inverse of [ParseToken::new_leaf].
+    /// Returns None for a branch. The token's tags borrow from this leaf, since
+    /// some of them may be owned.
+    pub fn as_token(&self) -> Option<Token<'_>> {
+        match &self.node {
+            ParseNode::Leaf(r) => Some(Token { body: self.body, indices: r.clone(), tags: self.tag_strs() }),
+            ParseNode::Branch(_) => None
+        }
+    }
+
+    /// Whether or not this token is a leaf, containing a single token's range.
+    pub fn is_leaf(&self) -> bool {
+        matches!(self.node, ParseNode::Leaf(_))
+    }
+
+    /// Whether or not this token is a branch, containing child parse tokens.
+    pub fn is_branch(&self) -> bool {
+        matches!(self.node, ParseNode::Branch(_))
+    }
+
+    /// If this token is a branch, returns its children. Otherwise, returns None.
+    pub fn children(&self) -> Option<&[ParseToken<'a>]> {
+        match &self.node {
+            ParseNode::Leaf(_) => None,
+            ParseNode::Branch(children) => Some(children)
+        }
+    }
+
+    /// If this token is a branch, returns its children by value, so they can be
+    /// moved into a new tree without cloning. Otherwise, returns the leaf back
+    /// as the error.
+    pub fn into_children(self) -> Result<Vec<ParseToken<'a>>, ParseToken<'a>> {
+        match self.node {
+            ParseNode::Branch(children) => Ok(children),
+            ParseNode::Leaf(_) => Err(self)
+        }
+    }
+
+    /// The `i`th child of this branch. Returns None for a leaf or an index out
+    /// of range.
+    pub fn nth_child(&self, i: usize) -> Option<&ParseToken<'a>> {
+        self.children()?.get(i)
+    }
+
+    /// The first child of this branch. Returns None for a leaf or an empty
+    /// branch.
+    pub fn first_child(&self) -> Option<&ParseToken<'a>> {
+        self.children()?.first()
+    }
+
+    /// The last child of this branch. Returns None for a leaf or an empty
+    /// branch.
+    pub fn last_child(&self) -> Option<&ParseToken<'a>> {
+        self.children()?.last()
+    }
+
+    /// Follows a path of child indices down from this token: an empty path
+    /// leads to this token, `[1]` to its second child, `[1, 0]` to that child's
+    /// first child, and so on. Returns None if the path leads nowhere.
+    pub fn subtree_at_path(&self, path: &[usize]) -> Option<&ParseToken<'a>> {
+        path.iter().try_fold(self, |pt, &i| pt.nth_child(i))
+    }
+
+    /// See [ParseToken::children]. Returns the children mutably, so that they
+    /// can be added, removed, or rewritten in place.
+    pub fn children_mut(&mut self) -> Option<&mut Vec<ParseToken<'a>>> {
+        match &mut self.node {
+            ParseNode::Leaf(_) => None,
+            ParseNode::Branch(children) => Some(children)
+        }
     }
 }
 
@@ -112,8 +579,37 @@ pub fn empty_parse_token() -> ParseToken<'static> {
     ParseToken::new_leaf(empty_token())
 }
 
+/// Turns each token into a leaf. Also appends an empty parse token (a
+/// sentinel) to the tail of the vector, in the same way as [str_to_tokens],
+/// which gives look-ahead rules something to inspect past the last real token.
+/// Rules that wait for the token after a run (as `word_rule` in the examples
+/// does with the sentinel from [str_to_tokens]) rely on it. Callers that don't
+/// need it can use [tokens_to_parse_tokens_maybe_sentinel] or
+/// [parse_tokens_from] instead.
 pub fn tokens_to_parse_tokens(tokens: Vec<Token>) -> Vec<ParseToken> {
-    let mut to_ret: Vec<ParseToken> = tokens.iter().map(|t| ParseToken::new_leaf(t.clone())).collect();
-    to_ret.push(empty_parse_token());
+    tokens_to_parse_tokens_maybe_sentinel(tokens, true)
+}
+
+/// See [tokens_to_parse_tokens]. Only appends the empty sentinel parse token if
+/// `sentinel` is true.
+pub fn tokens_to_parse_tokens_maybe_sentinel(tokens: Vec<Token>, sentinel: bool) -> Vec<ParseToken> {
+    let mut to_ret: Vec<ParseToken> = tokens.into_iter().map(ParseToken::new_leaf).collect();
+    if sentinel {
+        to_ret.push(empty_parse_token());
+    }
     to_ret
+}
+
+/// Turns each of a slice of tokens into a leaf, without appending a sentinel,
+/// so the result has exactly one parse token per token.
+pub fn parse_tokens_from<'a>(tokens: &[Token<'a>]) -> Vec<ParseToken<'a>> {
+    tokens.iter().map(|t| ParseToken {
+        node: ParseNode::Leaf(t.indices.clone()),
+        body: t.body,
+        tags: borrowed_tags(t.tags.clone())
+    }).collect()
+}
+
+fn borrowed_tags(tags: Vec<&str>) -> Vec<Cow<'_, str>> {
+    tags.into_iter().map(Cow::Borrowed).collect()
 }
\ No newline at end of file
@@ -0,0 +1,152 @@
+use super::parse_token::*;
+use std::ops::Range;
+
+/// Converts byte indices in a body into line and column numbers. Built once per
+/// body, after which each lookup is a binary search over the line starts.
+#[derive(Clone, Debug)]
+pub struct LineIndex<'a> {
+    body: &'a str,
+    /// The byte index at which each line begins, starting with 0.
+    line_starts: Vec<usize>
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(body: &'a str) -> LineIndex<'a> {
+        let line_starts = std::iter::once(0)
+            .chain(body.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { body, line_starts }
+    }
+
+    /// The line and column of a byte index in the body, both starting at 1.
+    /// Columns count characters, not bytes. An index past the end of the body
+    /// is treated as the end of the body.
+    pub fn line_col(&self, index: usize) -> (usize, usize) {
+        let index = index.min(self.body.len());
+        let line = self.line_starts.partition_point(|&start| start <= index);
+        let line_start = self.line_starts[line - 1];
+        let col = self.body.get(line_start..index).map_or(index - line_start, |s| s.chars().count());
+        (line, col + 1)
+    }
+}
+
+/// A range of bytes together with the body it indexes into, so that its text
+/// and position can be found without passing the body around separately.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span<'a> {
+    pub body: &'a str,
+    pub range: Range<usize>
+}
+
+impl<'a> Span<'a> {
+    pub fn new(body: &'a str, range: Range<usize>) -> Span<'a> {
+        Span { body, range }
+    }
+
+    /// The text of the body covered by this span, or an empty string if the
+    /// range is out of bounds or doesn't fall on character boundaries.
+    pub fn text(&self) -> &'a str {
+        self.body.get(self.range.clone()).unwrap_or_default()
+    }
+
+    /// The line and column (both starting at 1) at which this span begins.
+    pub fn line_col(&self, idx: &LineIndex) -> (usize, usize) {
+        idx.line_col(self.range.start)
+    }
+
+    /// The smallest span enclosing both this span and `other`, including any
+    /// gap between them. The result always has `start <= end`, even if either
+    /// input range is reversed. Keeps this span's body.
+    pub fn merge(&self, other: &Span) -> Span<'a> {
+        let start = self.range.start.min(self.range.end)
+            .min(other.range.start.min(other.range.end));
+        let end = self.range.start.max(self.range.end)
+            .max(other.range.start.max(other.range.end));
+        Span { body: self.body, range: start..end }
+    }
+}
+
+impl<'a> ParseToken<'a> {
+    /// The line and column (both starting at 1) at which this token's content
+    /// begins. Returns None if the token has no content range.
+    pub fn position(&self, idx: &LineIndex) -> Option<(usize, usize)> {
+        self.content_range().map(|r| idx.line_col(r.start))
+    }
+
+    /// This token's content range together with its body. See
+    /// [ParseToken::content_range].
+    pub fn span(&'a self) -> Option<Span<'a>> {
+        self.content_range().map(|r| Span::new(self.body, r))
+    }
+
+    /// The deepest leaf in this tree whose content range contains the byte
+    /// `offset`, for example to find what the cursor is on in an editor. At the
+    /// boundary between two leaves, the leaf that starts at `offset` wins, and
+    /// zero-width leaves (such as the sentinel) are never found. Returns None
+    /// if no leaf covers `offset`, even if the span of a branch does, as in a
+    /// reordered tree or one with gaps between its leaves.
+    pub fn token_at_byte(&self, offset: usize) -> Option<&ParseToken<'a>> {
+        let covers = |pt: &&ParseToken| pt.content_range().is_some_and(|r| r.contains(&offset));
+        match &self.node {
+            ParseNode::Leaf(r) => r.contains(&offset).then_some(self),
+            // only descend into children whose span covers the offset
+            ParseNode::Branch(children) => children.iter()
+                .filter(covers)
+                .find_map(|child| child.token_at_byte(offset))
+        }
+    }
+
+    /// Shows the line or lines of `body` that this token spans, each followed by
+    /// a line of carets (`^^^`) under the exact characters spanned, in the style
+    /// of a compiler error. A token spanning nothing but line breaks gets a
+    /// caret at the end of the line where it starts. Returns an empty string if
+    /// the token has no content range.
+    pub fn highlight(&self, body: &str) -> String {
+        let Some(r) = self.content_range() else {
+            return String::new();
+        };
+        let out = highlight_range(body, r.clone());
+        // a span of nothing but line breaks covers no visible characters, so
+        // point at the end of the line where it starts instead
+        if out.is_empty() && !r.is_empty() {
+            return highlight_range(body, r.start..r.start);
+        }
+        out
+    }
+}
+
+/// See [ParseToken::highlight]. An empty range gets a single caret on the line
+/// it sits on, placed at the end of the line if it points at a line break.
+fn highlight_range(body: &str, r: Range<usize>) -> String {
+    let mut out = String::new();
+    let mut line_start = 0;
+    for line in body.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        let line_end = line_start + text.len();
+        let (from, to) = if r.is_empty() {
+            (r.start.min(line_end), r.start.min(line_end))
+        } else {
+            (r.start.max(line_start), r.end.min(line_end))
+        };
+        let touched = if r.is_empty() {
+            (line_start..line_start + line.len()).contains(&r.start) || r.start == line_end
+        } else {
+            from < to
+        };
+        if touched {
+            let (from, to) = (from - line_start, to.max(from) - line_start);
+            let before = text.get(..from).unwrap_or("");
+            let under = text.get(from..to).unwrap_or("");
+            out.push_str(text);
+            out.push('\n');
+            out.extend(before.chars().map(|c| if c == '\t' { '\t' } else { ' ' }));
+            out.push_str(&"^".repeat(under.chars().count().max(1)));
+            out.push('\n');
+            if r.is_empty() {
+                break;
+            }
+        }
+        line_start += line.len();
+    }
+    out
+}
@@ -0,0 +1,79 @@
+use super::parse_token::*;
+use std::collections::HashMap;
+use std::iter::Peekable;
+
+/// Whether a chain of operators with the same binding power groups from the
+/// left (`a - b - c` as `(a - b) - c`) or from the right (`a ^ b ^ c` as
+/// `a ^ (b ^ c)`).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Associativity {
+    Left,
+    Right
+}
+
+/// Maps operator tags to their binding power and associativity. Operators with
+/// a higher binding power bind more tightly, and so end up deeper in the tree.
+#[derive(Clone, Default, Debug)]
+pub struct PrecedenceTable {
+    operators: HashMap<String, (u32, Associativity)>
+}
+
+impl PrecedenceTable {
+    pub fn new() -> PrecedenceTable {
+        PrecedenceTable::default()
+    }
+
+    /// Registers parse tokens tagged `tag` as infix operators.
+    pub fn add(&mut self, tag: &str, power: u32, assoc: Associativity) {
+        self.operators.insert(tag.to_string(), (power, assoc));
+    }
+
+    /// The binding power and associativity of a parse token, taken from its
+    /// first tag that names an operator. Returns None for operands.
+    pub fn lookup(&self, pt: &ParseToken) -> Option<(u32, Associativity)> {
+        pt.tags.iter().find_map(|t| self.operators.get(t.as_ref()).copied())
+    }
+}
+
+/// Parses a sequence of operands separated by infix operators into a single
+/// tree, folding each operator and its operands into a branch tagged
+/// `"binExpr"`. Operators are recognized by their tags using the precedence
+/// table; every other parse token is an operand.
+///
+/// Returns None if `pts` is empty or doesn't alternate between operands and
+/// operators, starting and ending with an operand, or if it uses a
+/// left-associative operator with a binding power of `u32::MAX`.
+pub fn parse_precedence<'a>(pts: Vec<ParseToken<'a>>, prec: &PrecedenceTable) -> Option<ParseToken<'a>> {
+    let mut pts = pts.into_iter().peekable();
+    let tree = parse_operand_chain(&mut pts, prec, 0)?;
+    if pts.peek().is_some() {
+        return None;
+    }
+    Some(tree)
+}
+
+/// Parses an operand followed by any operators (and their right-hand operands)
+/// that bind at least as tightly as `min_power`.
+fn parse_operand_chain<'a, I: Iterator<Item = ParseToken<'a>>>(pts: &mut Peekable<I>, prec: &PrecedenceTable, min_power: u32) -> Option<ParseToken<'a>> {
+    let mut lhs = pts.next()?;
+    if prec.lookup(&lhs).is_some() {
+        return None;
+    }
+
+    while let Some((power, assoc)) = pts.peek().and_then(|op| prec.lookup(op)) {
+        if power < min_power {
+            break;
+        }
+        // peek() returned Some, so there is an operator to take
+        let op = pts.next().unwrap();
+        let rhs_power = match assoc {
+            // nothing can bind tighter than u32::MAX, so a left-associative
+            // operator with that power has no valid right-hand side
+            Associativity::Left => power.checked_add(1)?,
+            Associativity::Right => power
+        };
+        let rhs = parse_operand_chain(pts, prec, rhs_power)?;
+        lhs = ParseToken::new_branch_from_first(vec![lhs, op, rhs], vec!["binExpr"]);
+    }
+    Some(lhs)
+}
@@ -0,0 +1,137 @@
+use super::parse_token::*;
+use std::collections::HashSet;
+
+/// How one step of a selector relates to the step before it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Combinator {
+    Child,
+    Descendant
+}
+
+/// Splits a selector into its steps, each a tag (or `*`) paired with how it
+/// relates to the previous step. Returns None for a malformed selector, such
+/// as an empty one or one starting or ending with `>`.
+fn parse_selector(selector: &str) -> Option<Vec<(Combinator, String)>> {
+    let mut steps = vec![];
+    let mut combinator = None;
+    for word in selector.replace('>', " > ").split_whitespace() {
+        if word == ">" {
+            if combinator.is_some() || steps.is_empty() {
+                return None;
+            }
+            combinator = Some(Combinator::Child);
+        } else {
+            steps.push((combinator.unwrap_or(Combinator::Descendant), word.to_string()));
+            combinator = None;
+        }
+    }
+    if steps.is_empty() || combinator.is_some() {
+        return None;
+    }
+    Some(steps)
+}
+
+impl<'a> ParseToken<'a> {
+    /// Collects every token in the tree rooted at this token (including the
+    /// token itself) that has the given tag, in preorder.
+    pub fn find_all_by_tag(&self, tag: &str) -> Vec<&ParseToken<'a>> {
+        self.iter_preorder().filter(|pt| pt.has_tag(tag)).collect()
+    }
+
+    /// The content of every token in the tree rooted at this token (including
+    /// the token itself) that has the given tag, in source order as given by
+    /// [ParseToken::cmp_position], even if the tree has been reordered. See
+    /// [ParseToken::find_all_by_tag] and [ParseToken::content].
+    pub fn collect_text_by_tag(&'a self, tag: &str) -> Vec<&'a str> {
+        let mut found = self.find_all_by_tag(tag);
+        found.sort_by(|a, b| a.cmp_position(b));
+        found.into_iter().map(|pt| pt.content()).collect()
+    }
+
+    /// Finds the first token in preorder (starting with this token) that has
+    /// the given tag. Stops searching as soon as a match is found, so the
+    /// outermost match is returned.
+    pub fn find_first_by_tag(&self, tag: &str) -> Option<&ParseToken<'a>> {
+        self.find_by(|pt| pt.has_tag(tag))
+    }
+
+    /// Finds the first token in preorder (starting with this token) for which
+    /// the predicate holds. The predicate receives the token itself, so it can
+    /// combine checks such as [ParseToken::has_tag] and [ParseToken::content].
+    pub fn find_by<F: Fn(&ParseToken<'a>) -> bool>(&self, pred: F) -> Option<&ParseToken<'a>> {
+        self.iter_preorder().find(|pt| pred(pt))
+    }
+
+    /// Whether any token in this tree (including this token) has the given tag.
+    pub fn contains_tag_anywhere(&self, tag: &str) -> bool {
+        self.find_first_by_tag(tag).is_some()
+    }
+
+    /// Whether every leaf in this tree has the given tag. True for a tree
+    /// without leaves.
+    pub fn all_leaves_have_tag(&self, tag: &str) -> bool {
+        self.leaves().all(|pt| pt.has_tag(tag))
+    }
+
+    /// Whether this branch has exactly one child per entry of `pattern`, each
+    /// having the tag at its position. A `"*"` entry matches any child. Always
+    /// false for a leaf.
+    pub fn children_match(&self, pattern: &[&str]) -> bool {
+        match self.children() {
+            Some(children) => children.len() == pattern.len() && children.iter()
+                .zip(pattern)
+                .all(|(c, tag)| *tag == "*" || c.has_tag(tag)),
+            None => false
+        }
+    }
+
+    /// Splits this branch's children into the groups between leaves with the
+    /// given tag, dropping those separator leaves. As with [str::split], two
+    /// separators in a row (or one at either end) produce an empty group, so a
+    /// trailing separator can be detected. A leaf has no groups.
+    pub fn split_children_on(&self, sep_tag: &str) -> Vec<Vec<&ParseToken<'a>>> {
+        match self.children() {
+            Some(children) => children
+                .split(|c| c.is_leaf() && c.has_tag(sep_tag))
+                .map(|group| group.iter().collect())
+                .collect(),
+            None => vec![]
+        }
+    }
+
+    /// Finds every token in this tree (including this token) matching a
+    /// CSS-like selector, in preorder. A selector is a sequence of tags, where
+    /// `*` matches any token. `a b` matches a `b` anywhere inside an `a`, and
+    /// `a > b` matches a `b` that is a direct child of an `a`. A malformed
+    /// selector matches nothing.
+    pub fn select(&self, selector: &str) -> Vec<&ParseToken<'a>> {
+        let Some(steps) = parse_selector(selector) else {
+            return vec![];
+        };
+        let matches = |pt: &ParseToken, tag: &str| tag == "*" || pt.has_tag(tag);
+
+        let (_, first) = &steps[0];
+        let mut current: Vec<&ParseToken<'a>> = self.iter_preorder().filter(|pt| matches(pt, first)).collect();
+        for (combinator, tag) in &steps[1..] {
+            let mut seen = HashSet::new();
+            let mut next = vec![];
+            for pt in current {
+                let candidates: Vec<&ParseToken<'a>> = match combinator {
+                    Combinator::Child => pt.children().unwrap_or_default().iter().collect(),
+                    Combinator::Descendant => pt.iter_preorder().skip(1).collect()
+                };
+                for c in candidates {
+                    if matches(c, tag) && seen.insert(c as *const ParseToken) {
+                        next.push(c);
+                    }
+                }
+            }
+            current = next;
+        }
+
+        // put the matches back into preorder, since nested candidates can be
+        // found out of order
+        let found: HashSet<*const ParseToken> = current.into_iter().map(|pt| pt as *const ParseToken).collect();
+        self.iter_preorder().filter(|pt| found.contains(&(*pt as *const ParseToken))).collect()
+    }
+}
@@ -0,0 +1,253 @@
+use super::parse_token::*;
+use super::error::*;
+use std::borrow::Cow;
+
+impl<'a> ParseToken<'a> {
+    /// Replaces every tag of every token in this tree (including this token)
+    /// with the result of applying `f` to it. `f` can return either a borrowed
+    /// tag or an owned `String`, so new tags can be built without converting
+    /// the tree to a [ParseTokenOwned](super::owned::ParseTokenOwned).
+    pub fn map_tags<T: Into<Cow<'a, str>>, F: Fn(Cow<'a, str>) -> T>(&mut self, f: F) {
+        let mut stack = vec![self];
+        while let Some(pt) = stack.pop() {
+            for tag in pt.tags.iter_mut() {
+                *tag = f(std::mem::take(tag)).into();
+            }
+            if let ParseNode::Branch(children) = &mut pt.node {
+                stack.extend(children.iter_mut());
+            }
+        }
+    }
+
+    /// Renames a tag on every token in this tree (including this token). The
+    /// new tag takes the place of the old one, unless the token already has
+    /// it, in which case the old tag is just removed.
+    pub fn rename_tag<T: Into<Cow<'a, str>>>(&mut self, from: &str, to: T) {
+        let to = to.into();
+        let mut stack = vec![self];
+        while let Some(pt) = stack.pop() {
+            if let Some(i) = pt.tags.iter().position(|t| t == from) {
+                if !pt.has_tag(&to) {
+                    pt.tags[i] = to.clone();
+                }
+                pt.remove_tag(from);
+            }
+            if let ParseNode::Branch(children) = &mut pt.node {
+                stack.extend(children.iter_mut());
+            }
+        }
+    }
+
+    /// Removes repeated tags from every token in this tree (including this
+    /// token), keeping the first occurrence of each.
+    pub fn dedup_tags(&mut self) {
+        let mut stack = vec![self];
+        while let Some(pt) = stack.pop() {
+            let mut kept: Vec<Cow<'a, str>> = Vec::with_capacity(pt.tags.len());
+            for tag in pt.tags.drain(..) {
+                if !kept.contains(&tag) {
+                    kept.push(tag);
+                }
+            }
+            pt.tags = kept;
+            if let ParseNode::Branch(children) = &mut pt.node {
+                stack.extend(children.iter_mut());
+            }
+        }
+    }
+
+    /// Walks this tree (including this token), adding the tag returned by `f`
+    /// to each token for which it returns Some. Tags already present are not
+    /// duplicated.
+    pub fn retag_by<F: Fn(&ParseToken<'a>) -> Option<&'a str>>(&mut self, f: F) {
+        let mut stack = vec![self];
+        while let Some(pt) = stack.pop() {
+            if let Some(tag) = f(pt) {
+                pt.add_tag(tag);
+            }
+            if let ParseNode::Branch(children) = &mut pt.node {
+                stack.extend(children.iter_mut());
+            }
+        }
+    }
+
+    /// Simplifies redundant branches, bottom-up: wherever a branch's only child
+    /// is itself a branch, the parent takes over the child's children and adds
+    /// the child's tags to its own. Leaves, and branches with several children
+    /// (or a single leaf), are left as they are.
+    pub fn collapse_single_child(&mut self) {
+        if let ParseNode::Branch(children) = &mut self.node {
+            children.iter_mut().for_each(|c| c.collapse_single_child());
+            if children.len() == 1 && children[0].is_branch() {
+                // len() == 1, so pop() always succeeds
+                let child = children.pop().unwrap();
+                for tag in child.tags {
+                    self.add_tag(tag);
+                }
+                self.body = child.body;
+                self.node = child.node;
+            }
+        }
+    }
+
+    /// Builds a new tree with the same branches, where every leaf is replaced
+    /// by the result of applying `f` to it. `f` should return a leaf itself, to
+    /// keep the tree well-formed.
+    pub fn map_leaves<F: Fn(&ParseToken<'a>) -> ParseToken<'a>>(self, f: F) -> ParseToken<'a> {
+        self.map_leaves_with(&f)
+    }
+
+    fn map_leaves_with<F: Fn(&ParseToken<'a>) -> ParseToken<'a>>(self, f: &F) -> ParseToken<'a> {
+        match self.node {
+            ParseNode::Leaf(_) => f(&self),
+            ParseNode::Branch(children) => ParseToken {
+                node: ParseNode::Branch(children.into_iter().map(|c| c.map_leaves_with(f)).collect()),
+                body: self.body,
+                tags: self.tags
+            }
+        }
+    }
+
+    /// Builds a copy of this tree with the same structure and ranges, where the
+    /// tags of every token (including this one) are replaced by the result of
+    /// applying `f` to the original token.
+    pub fn map_nodes<F: Fn(&ParseToken<'a>) -> Vec<&'a str>>(&self, f: F) -> ParseToken<'a> {
+        self.map_nodes_with(&f)
+    }
+
+    fn map_nodes_with<F: Fn(&ParseToken<'a>) -> Vec<&'a str>>(&self, f: &F) -> ParseToken<'a> {
+        let node = match &self.node {
+            ParseNode::Leaf(r) => ParseNode::Leaf(r.clone()),
+            ParseNode::Branch(children) => ParseNode::Branch(children.iter().map(|c| c.map_nodes_with(f)).collect())
+        };
+        ParseToken { node, body: self.body, tags: f(self).into_iter().map(Cow::Borrowed).collect() }
+    }
+
+    /// Removes every descendant of this token for which `pred` returns true,
+    /// along with its subtree. The token itself is never removed, even if it
+    /// matches.
+    pub fn prune_by<F: Fn(&ParseToken<'a>) -> bool>(&mut self, pred: F) {
+        let mut stack = vec![self];
+        while let Some(pt) = stack.pop() {
+            if let ParseNode::Branch(children) = &mut pt.node {
+                children.retain(|c| !pred(c));
+                stack.extend(children.iter_mut());
+            }
+        }
+    }
+
+    /// Keeps only the children of this branch for which `keep` returns true.
+    /// Unlike [ParseToken::prune_by], only direct children are considered. Does
+    /// nothing to a leaf.
+    pub fn retain_children<F: Fn(&ParseToken<'a>) -> bool>(&mut self, keep: F) {
+        if let Some(children) = self.children_mut() {
+            children.retain(|c| keep(c));
+        }
+    }
+
+    /// Replaces every token in this tree (including this token) for which
+    /// `pred` returns true with the result of applying `replace` to it.
+    ///
+    /// The tree is rewritten bottom-up: a branch's children are rewritten
+    /// before the branch itself is tested, so `replace` sees the rewritten
+    /// children, and a freshly inserted replacement is never searched again.
+    pub fn replace_subtree_where<F, G>(&mut self, pred: F, replace: G)
+    where
+        F: Fn(&ParseToken) -> bool,
+        G: Fn(&ParseToken<'a>) -> ParseToken<'a>
+    {
+        self.replace_subtree_with(&pred, &replace);
+    }
+
+    fn replace_subtree_with<F, G>(&mut self, pred: &F, replace: &G)
+    where
+        F: Fn(&ParseToken) -> bool,
+        G: Fn(&ParseToken<'a>) -> ParseToken<'a>
+    {
+        if let ParseNode::Branch(children) = &mut self.node {
+            for child in children {
+                child.replace_subtree_with(pred, replace);
+            }
+        }
+        if pred(self) {
+            *self = replace(self);
+        }
+    }
+
+    /// Inserts a child into this branch at the given index, shifting later
+    /// children to the right. Afterwards the branch borrows its body from its
+    /// first child, as [ParseToken::new_branch_from_first] does.
+    pub fn insert_child(&mut self, index: usize, child: ParseToken<'a>) -> Result<(), EditError> {
+        let ParseNode::Branch(children) = &mut self.node else {
+            return Err(EditError::NotABranch);
+        };
+        if index > children.len() {
+            return Err(EditError::OutOfRange { index, len: children.len() });
+        }
+        children.insert(index, child);
+        self.body = children[0].body;
+        Ok(())
+    }
+
+    /// Removes and returns the child of this branch at the given index.
+    /// Returns None for a leaf or an index out of range.
+    pub fn remove_child(&mut self, index: usize) -> Option<ParseToken<'a>> {
+        let children = self.children_mut()?;
+        if index >= children.len() {
+            return None;
+        }
+        let removed = children.remove(index);
+        if let Some(first) = children.first() {
+            self.body = first.body;
+        }
+        Some(removed)
+    }
+
+    /// Merges runs of this branch's children into single leaves. Two adjacent
+    /// children are merged if both are leaves, the first ends where the second
+    /// begins in the same body, and `can_merge` returns true for them. The
+    /// merged leaf spans both ranges and keeps the first leaf's tags, so a run
+    /// of several leaves becomes one. Does nothing to a leaf.
+    pub fn merge_adjacent_leaves<F: Fn(&ParseToken, &ParseToken) -> bool>(&mut self, can_merge: F) {
+        let Some(children) = self.children_mut() else {
+            return;
+        };
+        let mut merged: Vec<ParseToken<'a>> = Vec::with_capacity(children.len());
+        for child in children.drain(..) {
+            if let Some(prev) = merged.last_mut() {
+                if let (ParseNode::Leaf(a), ParseNode::Leaf(b)) = (&prev.node, &child.node) {
+                    if a.end == b.start && std::ptr::eq(prev.body, child.body) && can_merge(prev, &child) {
+                        prev.node = ParseNode::Leaf(a.start..b.end);
+                        continue;
+                    }
+                }
+            }
+            merged.push(child);
+        }
+        *children = merged;
+    }
+
+    /// Shifts the range of every leaf in this tree by `offset` bytes, to move
+    /// the tree to another position in a larger buffer. Bodies are left alone,
+    /// so the tree's content only makes sense once its tokens borrow from the
+    /// larger buffer. Returns false, leaving the tree unchanged, if a range
+    /// would start before 0 or end past `usize::MAX`.
+    pub fn reindex(&mut self, offset: isize) -> bool {
+        let fits = self.leaves().all(|leaf| match &leaf.node {
+            ParseNode::Leaf(r) => r.start.checked_add_signed(offset).is_some() && r.end.checked_add_signed(offset).is_some(),
+            ParseNode::Branch(_) => true
+        });
+        if !fits {
+            return false;
+        }
+        let mut stack = vec![self];
+        while let Some(pt) = stack.pop() {
+            match &mut pt.node {
+                // every range was checked above, so unwrap() is safe
+                ParseNode::Leaf(r) => *r = r.start.checked_add_signed(offset).unwrap()..r.end.checked_add_signed(offset).unwrap(),
+                ParseNode::Branch(children) => stack.extend(children.iter_mut())
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,175 @@
+pub use blex::*;
+use super::error::*;
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+
+/// Combines several rules into one that tries each rule in order and uses the
+/// result of the first one that changes the tokens. Since rules like
+/// "tag whitespace" return their input unchanged when they don't apply, this
+/// lets several of them share a single pass.
+///
+/// If no rule changes the tokens, the combined rule returns None if any rule
+/// asked for more tokens by returning None, and the unchanged tokens otherwise.
+pub fn rule_or<R: Fn(Vec<Token>) -> Option<Vec<Token>>>(rules: Vec<R>) -> impl Fn(Vec<Token>) -> Option<Vec<Token>> {
+    move |tokens| {
+        let mut wants_more = false;
+        for rule in &rules {
+            match rule(tokens.clone()) {
+                Some(applied) if applied != tokens => return Some(applied),
+                Some(_) => {},
+                None => wants_more = true
+            }
+        }
+        if wants_more {
+            None
+        } else {
+            Some(tokens)
+        }
+    }
+}
+
+/// Combines several rules into one that threads the tokens through each rule
+/// in order. If any rule returns None, the combined rule does too. Once a rule
+/// removes every token, the remaining rules are skipped, since most rules
+/// assume they are given at least one token.
+pub fn rule_seq<R: Fn(Vec<Token>) -> Option<Vec<Token>>>(rules: Vec<R>) -> impl Fn(Vec<Token>) -> Option<Vec<Token>> {
+    move |mut tokens| {
+        for rule in &rules {
+            if tokens.is_empty() {
+                break;
+            }
+            tokens = rule(tokens)?;
+        }
+        Some(tokens)
+    }
+}
+
+/// A rule chosen at runtime, as returned by [rules_from_spec].
+pub type BoxedRule = Box<dyn Fn(Vec<Token>) -> Option<Vec<Token>>>;
+
+/// A single-character test from a rule spec.
+#[derive(Clone, Copy)]
+enum CharPattern {
+    Literal(char),
+    Whitespace,
+    Alphabetic,
+    Numeric,
+    Alphanumeric,
+    End
+}
+
+impl CharPattern {
+    fn matches(self, tok: &Token) -> bool {
+        let ch = tok.single_char();
+        match self {
+            CharPattern::Literal(c) => ch == Some(c),
+            CharPattern::Whitespace => ch.is_some_and(char::is_whitespace),
+            CharPattern::Alphabetic => ch.is_some_and(char::is_alphabetic),
+            CharPattern::Numeric => ch.is_some_and(char::is_numeric),
+            CharPattern::Alphanumeric => ch.is_some_and(char::is_alphanumeric),
+            CharPattern::End => tok.indices.is_empty()
+        }
+    }
+}
+
+/// Builds simple tagging rules from a spec with one rule per line, in the form
+/// `tag: pattern | pattern | ...`. Each rule adds its tag to any single
+/// character token matching one of its patterns, which are either a quoted
+/// character such as `'('` or one of the classes `whitespace`, `alphabetic`,
+/// `numeric`, `alphanumeric`, and `end` (the empty token that [str_to_tokens]
+/// appends). Blank lines are ignored. For example:
+///
+/// ```text
+/// ws: whitespace | end
+/// paren: '(' | ')'
+/// ```
+///
+/// The tags are borrowed from the spec, so it must be `'static`, such as a
+/// literal: blex's [process_rules] takes rules that work on tokens of any
+/// lifetime, and a token's tags share its lifetime. For a spec built at
+/// runtime, see [rules_from_spec_owned]. Returns an error pointing into the
+/// spec if a line is malformed.
+pub fn rules_from_spec(spec: &'static str) -> Result<Vec<BoxedRule>, ParseError<'static>> {
+    Ok(parse_spec(spec)?.into_iter().map(|(tag, patterns)| tagging_rule(tag, patterns)).collect())
+}
+
+/// See [rules_from_spec]. Takes a spec of any lifetime, such as one read from
+/// a file, by copying each tag out of it. Since the rules must be able to add
+/// their tags to tokens of any lifetime, the copies live for the rest of the
+/// program, but each distinct tag is only copied once, however many times this
+/// is called. Nothing is copied if the spec is malformed.
+pub fn rules_from_spec_owned(spec: &str) -> Result<Vec<BoxedRule>, ParseError<'static>> {
+    Ok(parse_spec(spec)?.into_iter()
+        .map(|(tag, patterns)| tagging_rule(intern(tag), patterns))
+        .collect())
+}
+
+/// A `'static` copy of `tag`, shared by every call with the same tag.
+fn intern(tag: &str) -> &'static str {
+    static TAGS: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut tags = TAGS.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(&interned) = tags.get(tag) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(tag.into());
+    tags.insert(interned);
+    interned
+}
+
+/// A rule that adds `tag` to any token matching one of `patterns`.
+fn tagging_rule(tag: &'static str, patterns: Vec<CharPattern>) -> BoxedRule {
+    Box::new(move |mut tokens: Vec<Token>| {
+        if patterns.iter().any(|p| p.matches(&tokens[0])) {
+            tokens[0].tags.push(tag);
+        }
+        Some(tokens)
+    })
+}
+
+/// Splits a spec into its tags and their patterns. See [rules_from_spec].
+fn parse_spec(spec: &str) -> Result<Vec<(&str, Vec<CharPattern>)>, ParseError<'static>> {
+    // where a piece of the spec starts, for error spans
+    let offset = |piece: &str| piece.as_ptr() as usize - spec.as_ptr() as usize;
+    let span = |piece: &str| offset(piece)..offset(piece) + piece.len();
+
+    let mut rules = vec![];
+    for line in spec.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((tag, patterns)) = line.split_once(':') else {
+            return Err(ParseError::new("expected \"tag: pattern\"", span(line)));
+        };
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return Err(ParseError::new("missing tag", span(line)));
+        }
+        let patterns = patterns.split('|')
+            .map(|p| parse_char_pattern(p.trim()).ok_or_else(|| {
+                ParseError::new(format!("unknown pattern {:?}", p.trim()), span(p.trim()))
+            }))
+            .collect::<Result<Vec<_>, _>>()?;
+        rules.push((tag, patterns));
+    }
+    Ok(rules)
+}
+
+fn parse_char_pattern(p: &str) -> Option<CharPattern> {
+    let quoted = p.strip_prefix('\'').and_then(|p| p.strip_suffix('\''));
+    if let Some(inner) = quoted {
+        let mut chars = inner.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(CharPattern::Literal(c)),
+            _ => None
+        };
+    }
+    match p {
+        "whitespace" => Some(CharPattern::Whitespace),
+        "alphabetic" => Some(CharPattern::Alphabetic),
+        "numeric" => Some(CharPattern::Numeric),
+        "alphanumeric" => Some(CharPattern::Alphanumeric),
+        "end" => Some(CharPattern::End),
+        _ => None
+    }
+}
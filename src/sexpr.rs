@@ -0,0 +1,70 @@
+use super::parse_token::*;
+
+/// Tags whitespace characters and the trailing sentinel with `"ws"`.
+pub fn whitespace_rule(mut tokens: Vec<Token>) -> Option<Vec<Token>> {
+    let ch = tokens[0].single_char().unwrap_or_default();
+    if ch.is_whitespace() || ch == '\u{0}' {
+        tokens[0].tags.push("ws");
+    }
+    Some(tokens)
+}
+
+/// Tags parentheses with `"paren"`.
+pub fn paren_rule(mut tokens: Vec<Token>) -> Option<Vec<Token>> {
+    let ch = tokens[0].single_char().unwrap_or_default();
+    if ch == '(' || ch == ')' {
+        tokens[0].tags.push("paren");
+    }
+    Some(tokens)
+}
+
+/// Wraps each run of characters up to the next whitespace or parenthesis into
+/// a token tagged `"word"`.
+pub fn word_rule(tokens: Vec<Token>) -> Option<Vec<Token>> {
+    let empty = &empty_token();
+    let last_token = tokens.last().unwrap_or(empty);
+    if !last_token.has_tag("ws") && !last_token.has_tag("paren") {
+        None
+    } else if tokens.len() == 1 {
+        Some(tokens)
+    } else {
+        Some(vec![
+            wrap(tokens[0..tokens.len() - 1].to_vec(), vec!["word"]),
+            tokens.last().unwrap().clone(),
+        ])
+    }
+}
+
+/// Deletes tokens tagged `"ws"`.
+pub fn remove_whitespace_rule(tokens: Vec<Token>) -> Option<Vec<Token>> {
+    if tokens[0].has_tag("ws") {
+        Some(vec![])
+    } else {
+        Some(tokens)
+    }
+}
+
+/// Rules that split s-expression source into words and parentheses, dropping
+/// the whitespace between them.
+pub fn s_expr_rules() -> Vec<impl Fn(Vec<Token>) -> Option<Vec<Token>>> {
+    [
+        whitespace_rule,
+        paren_rule,
+        word_rule,
+        remove_whitespace_rule,
+    ]
+    .to_vec()
+}
+
+/// Like [s_expr_rules], but keeps whitespace as tokens tagged `"ws"`, so that
+/// [ParseToken::to_source] can reproduce the original spacing. Bracket matching
+/// treats these as ordinary children, so they end up inside the groups that
+/// surround them.
+pub fn s_expr_rules_preserving() -> Vec<impl Fn(Vec<Token>) -> Option<Vec<Token>>> {
+    [
+        whitespace_rule,
+        paren_rule,
+        word_rule,
+    ]
+    .to_vec()
+}
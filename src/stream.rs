@@ -0,0 +1,73 @@
+use super::error::*;
+use super::grouping;
+use super::parse_token::*;
+use super::process_parse_rules;
+
+/// A vector of parse tokens that keeps the empty sentinel described in
+/// [tokens_to_parse_tokens] to itself. Rules applied to the stream still see
+/// the sentinel at the end, so look-ahead rules work, but [ParseStream::tokens]
+/// never includes it.
+#[derive(Clone)]
+pub struct ParseStream<'a> {
+    /// Always ends with the sentinel.
+    pts: Vec<ParseToken<'a>>
+}
+
+/// Whether a parse token is empty, like the sentinels from [str_to_tokens] and
+/// [tokens_to_parse_tokens].
+fn is_sentinel(pt: &ParseToken) -> bool {
+    pt.is_leaf() && pt.content().is_empty()
+}
+
+impl<'a> ParseStream<'a> {
+    /// Turns each token into a leaf. If the last token is empty, it is taken to
+    /// be the sentinel from [str_to_tokens] and dropped.
+    pub fn from_tokens(tokens: Vec<Token<'a>>) -> ParseStream<'a> {
+        ParseStream::from_parse_tokens(tokens.into_iter().map(ParseToken::from).collect())
+    }
+
+    /// Wraps existing parse tokens. If the last parse token is empty, it is
+    /// taken to be the sentinel and dropped; empty parse tokens before it are
+    /// kept.
+    pub fn from_parse_tokens(mut pts: Vec<ParseToken<'a>>) -> ParseStream<'a> {
+        if pts.last().is_some_and(is_sentinel) {
+            pts.pop();
+        }
+        pts.push(empty_parse_token());
+        ParseStream { pts }
+    }
+
+    /// Adds the sentinel back if a rule removed it. Only the last parse token
+    /// can be the stream's sentinel, so empty parse tokens before it are kept.
+    fn restore_sentinel(&mut self) {
+        if !self.pts.last().is_some_and(is_sentinel) {
+            self.pts.push(empty_parse_token());
+        }
+    }
+
+    /// The parse tokens in the stream, without the sentinel.
+    pub fn tokens(&self) -> &[ParseToken<'a>] {
+        &self.pts[..self.pts.len() - 1]
+    }
+
+    /// See [ParseStream::tokens].
+    pub fn into_tokens(mut self) -> Vec<ParseToken<'a>> {
+        self.pts.pop();
+        self.pts
+    }
+
+    /// Applies rules to the stream, sentinel included, as
+    /// [process_parse_rules] does.
+    pub fn apply_rules<F: Fn(Vec<ParseToken>) -> Option<Vec<ParseToken>>>(&mut self, rules: Vec<F>) {
+        process_parse_rules(rules, &mut self.pts, false);
+        self.restore_sentinel();
+    }
+
+    /// Groups the stream's brackets as [grouping::match_brackets] does. If the
+    /// brackets are unbalanced, the stream is left unchanged.
+    pub fn match_brackets(&mut self, open_tag: &str, close_tag: &str, wrap_tags: Vec<&'a str>) -> Result<(), ParseError<'a>> {
+        self.pts = grouping::match_brackets(self.pts.clone(), open_tag, close_tag, wrap_tags)?;
+        self.restore_sentinel();
+        Ok(())
+    }
+}
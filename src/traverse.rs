@@ -0,0 +1,158 @@
+use std::collections::{HashMap, VecDeque};
+use super::parse_token::*;
+
+/// Walks a tree of parse tokens depth-first, yielding each token before its
+/// children. Uses an explicit stack instead of recursion, so deeply nested
+/// trees can't overflow the call stack.
+struct Preorder<'t, 'a> {
+    stack: Vec<&'t ParseToken<'a>>
+}
+
+impl<'t, 'a> Iterator for Preorder<'t, 'a> {
+    type Item = &'t ParseToken<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pt = self.stack.pop()?;
+        if let ParseNode::Branch(children) = &pt.node {
+            self.stack.extend(children.iter().rev());
+        }
+        Some(pt)
+    }
+}
+
+/// Walks a tree of parse tokens depth-first, yielding each token after all of
+/// its children. Each stack entry holds a token and the index of the next child
+/// to visit.
+struct Postorder<'t, 'a> {
+    stack: Vec<(&'t ParseToken<'a>, usize)>
+}
+
+impl<'t, 'a> Iterator for Postorder<'t, 'a> {
+    type Item = &'t ParseToken<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (pt, next_child) = self.stack.last_mut()?;
+            let pt: &'t ParseToken<'a> = pt;
+            match &pt.node {
+                ParseNode::Branch(children) if *next_child < children.len() => {
+                    let child = &children[*next_child];
+                    *next_child += 1;
+                    self.stack.push((child, 0));
+                },
+                _ => {
+                    self.stack.pop();
+                    return Some(pt);
+                }
+            }
+        }
+    }
+}
+
+/// Walks a tree of parse tokens breadth-first, yielding every token on one level
+/// before any token on the next.
+struct Bfs<'t, 'a> {
+    queue: VecDeque<&'t ParseToken<'a>>
+}
+
+impl<'t, 'a> Iterator for Bfs<'t, 'a> {
+    type Item = &'t ParseToken<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pt = self.queue.pop_front()?;
+        if let ParseNode::Branch(children) = &pt.node {
+            self.queue.extend(children.iter());
+        }
+        Some(pt)
+    }
+}
+
+impl<'a> ParseToken<'a> {
+    /// Iterates over this token and all of its descendants, yielding each
+    /// token before its children, which are visited in order.
+    pub fn iter_preorder(&self) -> impl Iterator<Item = &ParseToken<'a>> {
+        Preorder { stack: vec![self] }
+    }
+
+    /// Iterates over this token and all of its descendants, yielding each
+    /// token after its children. The token itself always comes last.
+    pub fn iter_postorder(&self) -> impl Iterator<Item = &ParseToken<'a>> {
+        Postorder { stack: vec![(self, 0)] }
+    }
+
+    /// Iterates over this token and all of its descendants level by level,
+    /// starting with the token itself.
+    pub fn iter_bfs(&self) -> impl Iterator<Item = &ParseToken<'a>> {
+        Bfs { queue: VecDeque::from([self]) }
+    }
+
+    /// Iterates over every leaf reachable from this token, in order from left
+    /// to right. A leaf yields only itself.
+    pub fn leaves(&self) -> impl Iterator<Item = &ParseToken<'a>> {
+        self.iter_preorder().filter(|pt| pt.is_leaf())
+    }
+
+    /// The height of the tree rooted at this token. A leaf (or a branch with no
+    /// children) has a depth of 1; a branch is one deeper than its deepest
+    /// child. Computed without recursion.
+    pub fn depth(&self) -> usize {
+        let mut max = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((pt, level)) = stack.pop() {
+            max = max.max(level);
+            if let ParseNode::Branch(children) = &pt.node {
+                stack.extend(children.iter().map(|c| (c, level + 1)));
+            }
+        }
+        max
+    }
+
+    /// The number of parse tokens in the tree rooted at this token, including
+    /// the token itself.
+    pub fn node_count(&self) -> usize {
+        self.iter_preorder().count()
+    }
+
+    /// The number of leaves in the tree rooted at this token. Agrees with the
+    /// number of items yielded by [ParseToken::leaves].
+    pub fn leaf_count(&self) -> usize {
+        self.leaves().count()
+    }
+
+    /// Counts how many times each tag appears across every token in this tree
+    /// (including this token).
+    pub fn tag_histogram(&self) -> HashMap<&str, usize> {
+        let mut counts = HashMap::new();
+        for pt in self.iter_preorder() {
+            for tag in &pt.tags {
+                *counts.entry(tag.as_ref()).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// Reduces this tree to a single value, bottom-up. Each leaf is turned into
+    /// a value by `leaf_fn`, and each branch by `branch_fn`, which receives the
+    /// branch along with the values of its children, in order.
+    pub fn fold<B, L, Br>(&self, leaf_fn: L, branch_fn: Br) -> B
+    where
+        L: Fn(&ParseToken<'a>) -> B,
+        Br: Fn(&ParseToken<'a>, Vec<B>) -> B
+    {
+        self.fold_with(&leaf_fn, &branch_fn)
+    }
+
+    fn fold_with<B, L, Br>(&self, leaf_fn: &L, branch_fn: &Br) -> B
+    where
+        L: Fn(&ParseToken<'a>) -> B,
+        Br: Fn(&ParseToken<'a>, Vec<B>) -> B
+    {
+        match &self.node {
+            ParseNode::Leaf(_) => leaf_fn(self),
+            ParseNode::Branch(children) => {
+                let values = children.iter().map(|c| c.fold_with(leaf_fn, branch_fn)).collect();
+                branch_fn(self, values)
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use super::parse_token::*;
+
+/// An index over a borrowed tree of parse tokens that records each token's
+/// parent, so that upward queries ("what encloses this leaf?") can be answered
+/// without adding back-pointers to the tree itself.
+///
+/// Each token is identified by its id, its position in a preorder traversal of
+/// the tree; the root's id is 0.
+#[derive(Clone)]
+pub struct TreeIndex<'t, 'a> {
+    nodes: Vec<&'t ParseToken<'a>>,
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>
+}
+
+impl<'t, 'a> TreeIndex<'t, 'a> {
+    pub fn new(root: &'t ParseToken<'a>) -> TreeIndex<'t, 'a> {
+        let mut nodes = vec![];
+        let mut parents = vec![];
+        let mut children: Vec<Vec<usize>> = vec![];
+        let mut stack: Vec<(&ParseToken, Option<usize>)> = vec![(root, None)];
+        while let Some((pt, parent)) = stack.pop() {
+            let id = nodes.len();
+            nodes.push(pt);
+            parents.push(parent);
+            children.push(vec![]);
+            if let Some(p) = parent {
+                children[p].push(id);
+            }
+            if let Some(children) = pt.children() {
+                stack.extend(children.iter().rev().map(|c| (c, Some(id))));
+            }
+        }
+        TreeIndex { nodes, parents, children }
+    }
+
+    /// The number of tokens in the tree.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Always false, since the tree has at least a root.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The token with the given id.
+    pub fn node(&self, id: usize) -> Option<&'t ParseToken<'a>> {
+        self.nodes.get(id).copied()
+    }
+
+    /// The id of a token in the tree, found by identity rather than equality.
+    pub fn id_of(&self, pt: &ParseToken<'a>) -> Option<usize> {
+        self.nodes.iter().position(|n| std::ptr::eq(*n, pt))
+    }
+
+    /// The id of the branch containing the given token. Returns None for the
+    /// root.
+    pub fn parent(&self, id: usize) -> Option<usize> {
+        self.parents.get(id).copied().flatten()
+    }
+
+    /// The ids of every branch containing the given token, from its parent up
+    /// to the root.
+    pub fn ancestors(&self, id: usize) -> Vec<usize> {
+        std::iter::successors(self.parent(id), |&p| self.parent(p)).collect()
+    }
+
+    /// The id of the closest branch containing the given token that has the
+    /// given tag, searching from its parent up to the root.
+    pub fn nearest_ancestor_with_tag(&self, id: usize, tag: &str) -> Option<usize> {
+        std::iter::successors(self.parent(id), |&p| self.parent(p)).find(|&a| self.nodes[a].has_tag(tag))
+    }
+
+    /// The ids of the children of the given token, in order.
+    pub fn children(&self, id: usize) -> &[usize] {
+        self.children.get(id).map_or(&[], |c| c.as_slice())
+    }
+
+    /// The ids of the other children of the given token's parent, in order.
+    /// The root has no siblings.
+    pub fn siblings(&self, id: usize) -> Vec<usize> {
+        match self.parent(id) {
+            Some(p) => self.children(p).iter().copied().filter(|&s| s != id).collect(),
+            None => vec![]
+        }
+    }
+
+    /// See [TreeIndex::siblings]. Only includes siblings with the given tag.
+    pub fn siblings_with_tag(&self, id: usize, tag: &str) -> Vec<usize> {
+        self.siblings(id).into_iter().filter(|&s| self.nodes[s].has_tag(tag)).collect()
+    }
+
+    /// The child indices leading from the root to the given token, for use with
+    /// [ParseToken::subtree_at_path]. The root's path is empty. Returns None
+    /// for an id that isn't in the tree.
+    pub fn path_of(&self, id: usize) -> Option<Vec<usize>> {
+        if id >= self.len() {
+            return None;
+        }
+        let mut path = vec![];
+        let mut child = id;
+        while let Some(p) = self.parent(child) {
+            // every child is listed under its parent, so unwrap() is safe
+            path.push(self.children(p).iter().position(|&c| c == child).unwrap());
+            child = p;
+        }
+        path.reverse();
+        Some(path)
+    }
+}
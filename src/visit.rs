@@ -0,0 +1,61 @@
+use super::parse_token::*;
+
+/// A stateful traversal over a tree of parse tokens, driven by
+/// [ParseToken::accept]. By default, visiting a branch visits each of its
+/// children in order, and visiting a leaf does nothing, so implementors only
+/// need to override the methods they care about. An overridden
+/// [Visitor::visit_branch] can call [walk_children] to keep recursing.
+pub trait Visitor<'a> {
+    fn visit_leaf(&mut self, _leaf: &ParseToken<'a>) {}
+
+    fn visit_branch(&mut self, branch: &ParseToken<'a>) {
+        walk_children(self, branch);
+    }
+}
+
+/// Visits each child of a branch in order. Does nothing for a leaf.
+pub fn walk_children<'a, V: Visitor<'a> + ?Sized>(v: &mut V, branch: &ParseToken<'a>) {
+    for child in branch.children().unwrap_or_default() {
+        child.accept(v);
+    }
+}
+
+/// What [ParseToken::walk_mut] should do after visiting a token.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Descent {
+    /// Go on to visit the token's children.
+    Into,
+    /// Move past the token's children without visiting them.
+    Skip,
+    /// End the walk.
+    Stop
+}
+
+impl<'a> ParseToken<'a> {
+    /// Calls the visitor method matching this token's kind.
+    pub fn accept<V: Visitor<'a> + ?Sized>(&self, v: &mut V) {
+        match &self.node {
+            ParseNode::Leaf(_) => v.visit_leaf(self),
+            ParseNode::Branch(_) => v.visit_branch(self)
+        }
+    }
+
+    /// Visits this token and then its descendants in preorder, letting `f`
+    /// change each one in place. What `f` returns decides whether the walk goes
+    /// into the token's children (which `f` may have changed), skips them, or
+    /// stops.
+    pub fn walk_mut<F: FnMut(&mut ParseToken<'a>) -> Descent>(&mut self, mut f: F) {
+        let mut stack = vec![self];
+        while let Some(pt) = stack.pop() {
+            match f(pt) {
+                Descent::Into => {
+                    if let ParseNode::Branch(children) = &mut pt.node {
+                        stack.extend(children.iter_mut().rev());
+                    }
+                },
+                Descent::Skip => {},
+                Descent::Stop => return
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use super::parse_token::*;
+use std::borrow::Cow;
+
+/// What the zipper remembers about a branch it has moved down into: everything
+/// except the focused child, so the branch can be rebuilt on the way back up.
+#[derive(Clone)]
+struct Crumb<'a> {
+    body: &'a str,
+    tags: Vec<Cow<'a, str>>,
+    left: Vec<ParseToken<'a>>,
+    /// The siblings to the right of the focus, nearest last.
+    right: Vec<ParseToken<'a>>
+}
+
+/// A cursor into a tree of parse tokens that can move around the tree and edit
+/// the token it is focused on. Edits are carried back up through every ancestor
+/// when the zipper moves up, so a change deep in the tree doesn't require
+/// rebuilding each ancestor by hand.
+///
+/// Movement methods return whether the move was possible; a failed move leaves
+/// the zipper where it was.
+#[derive(Clone)]
+pub struct ParseZipper<'a> {
+    focus: ParseToken<'a>,
+    path: Vec<Crumb<'a>>
+}
+
+impl<'a> ParseZipper<'a> {
+    /// Creates a zipper focused on the root of a tree.
+    pub fn new(root: ParseToken<'a>) -> ParseZipper<'a> {
+        ParseZipper { focus: root, path: vec![] }
+    }
+
+    /// The token currently in focus.
+    pub fn node(&self) -> &ParseToken<'a> {
+        &self.focus
+    }
+
+    /// Replaces the token in focus, returning the old one.
+    pub fn replace(&mut self, pt: ParseToken<'a>) -> ParseToken<'a> {
+        std::mem::replace(&mut self.focus, pt)
+    }
+
+    /// Moves the focus to the `i`th child of the focused branch.
+    pub fn down(&mut self, i: usize) -> bool {
+        match &mut self.focus.node {
+            ParseNode::Branch(children) if i < children.len() => {
+                let mut left = std::mem::take(children);
+                let mut right = left.split_off(i + 1);
+                right.reverse();
+                // i < len, so there is a child to pop
+                let child = left.pop().unwrap();
+                let parent = std::mem::replace(&mut self.focus, child);
+                self.path.push(Crumb { body: parent.body, tags: parent.tags, left, right });
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Moves the focus to the parent of the focused token.
+    pub fn up(&mut self) -> bool {
+        match self.path.pop() {
+            Some(crumb) => {
+                let mut children = crumb.left;
+                children.push(std::mem::replace(&mut self.focus, empty_parse_token()));
+                children.extend(crumb.right.into_iter().rev());
+                self.focus = ParseToken { node: ParseNode::Branch(children), body: crumb.body, tags: crumb.tags };
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Moves the focus to the previous sibling of the focused token.
+    pub fn left(&mut self) -> bool {
+        match self.path.last_mut() {
+            Some(crumb) if !crumb.left.is_empty() => {
+                // checked that left is not empty
+                let sibling = crumb.left.pop().unwrap();
+                crumb.right.push(std::mem::replace(&mut self.focus, sibling));
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Moves the focus to the next sibling of the focused token.
+    pub fn right(&mut self) -> bool {
+        match self.path.last_mut() {
+            Some(crumb) if !crumb.right.is_empty() => {
+                // checked that right is not empty
+                let sibling = crumb.right.pop().unwrap();
+                crumb.left.push(std::mem::replace(&mut self.focus, sibling));
+                true
+            },
+            _ => false
+        }
+    }
+
+    /// Moves back up to the root and returns the whole, edited tree.
+    pub fn into_tree(mut self) -> ParseToken<'a> {
+        while self.up() {}
+        self.focus
+    }
+}